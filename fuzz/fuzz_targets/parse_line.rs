@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use snakerunner::{GameLog, Instruction};
+
+// Lines come from bots and log files, so parsing them must never panic, and whatever parses
+// has to be written back the same way
+fuzz_target!(|data: &str| {
+    if let Ok(instruction) = data.parse::<Instruction>() {
+        assert_eq!(instruction.to_string().parse(), Ok(instruction.clone()));
+        assert_eq!(
+            Instruction::from_json(&instruction.to_json()),
+            Ok(instruction)
+        );
+    }
+    if let Ok(instruction) = Instruction::from_json(data) {
+        assert_eq!(
+            Instruction::from_json(&instruction.to_json()),
+            Ok(instruction)
+        );
+    }
+    let _ = data.parse::<GameLog>();
+});
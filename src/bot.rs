@@ -0,0 +1,487 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::builtin::{builtin_bot, BUILTIN_BOTS};
+use crate::clock::MoveLimit;
+use crate::launcher::Launchers;
+use crate::protocol::PROTOCOL_VERSION;
+use crate::strategy::{Bot, BotFactory};
+use crate::transport::Lobby;
+
+/// A player in a game: how to start it and the name it goes by in output, logs and summaries
+#[derive(Clone, Debug, PartialEq)]
+pub struct BotSpec {
+    pub name: String,
+    pub source: BotSource,
+    /// Version of the protocol to speak to the bot, version 1 if `None`. Only bots that speak
+    /// version 2 are greeted with the handshake, and cannot play if they do not answer it.
+    pub protocol: Option<u32>,
+}
+
+/// How the process of a bot is started
+#[derive(Clone, Debug, PartialEq)]
+pub enum BotSource {
+    /// A script that is started by the launcher matching its name
+    Script(String),
+    /// An explicit command: the program followed by its arguments, run in `cwd` if given
+    Command {
+        args: Vec<String>,
+        cwd: Option<PathBuf>,
+    },
+    /// A bot listening on a TCP socket, e.g. `127.0.0.1:9000`
+    Tcp(String),
+    /// A bot listening on a Unix domain socket
+    Unix(PathBuf),
+    /// A bot that connects to the lobby and identifies itself with the name of the bot
+    Lobby(Arc<Lobby>),
+    /// A person playing in the terminal, with `time_limit` milliseconds per move if given
+    Human { time_limit: Option<u64> },
+    /// A bot written in Rust that plays in a thread of the runner, e.g. a built-in bot
+    InProcess(BotFactory),
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum BotSpecError {
+    #[error("Bot has neither a cmd, a script, an addr, human nor builtin")]
+    MissingCommand,
+    #[error("Bot has more than one of cmd, script, addr, human and builtin")]
+    MultipleSources,
+    #[error("Invalid addr \"{0}\", expected tcp://{{host}}:{{port}} or unix://{{path}}")]
+    InvalidAddress(String),
+    #[error("Unknown key \"{0}\" in bot, expected name, cmd, script, addr, human, builtin, cwd or protocol")]
+    UnknownKey(String),
+    #[error("Invalid time limit \"{0}\" for human, expected milliseconds")]
+    InvalidTimeLimit(String),
+    #[error("Unknown built-in bot \"{0}\", expected {}", BUILTIN_BOTS.join(" or "))]
+    UnknownBuiltin(String),
+    #[error("A cwd can only be given along with a cmd")]
+    CwdWithoutCommand,
+    #[error("Unsupported protocol version \"{0}\", expected 1 or {PROTOCOL_VERSION}")]
+    InvalidProtocol(String),
+}
+
+impl BotSpec {
+    /// A bot that runs a script and is named after it
+    pub fn script(script: impl Into<String>) -> Self {
+        let script = script.into();
+        Self {
+            name: script.clone(),
+            source: BotSource::Script(script),
+            protocol: None,
+        }
+    }
+
+    /// A bot named after `name`, which is a socket address (`tcp://{host}:{port}` or
+    /// `unix://{path}`), `human` or `human:{time limit}` for a person playing in the terminal,
+    /// `builtin:{name}` for a built-in bot, or else the file name of a script
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let source = parse_address(&name)
+            .or_else(|| parse_human(&name)?.ok())
+            .or_else(|| parse_builtin(&name)?.ok());
+        match source {
+            Some(source) => Self {
+                name,
+                source,
+                protocol: None,
+            },
+            None => Self::script(name),
+        }
+    }
+
+    /// Like `new`, but fails for a human with an invalid time limit or an unknown built-in bot,
+    /// instead of taking them to be scripts
+    pub fn parse_name(name: &str) -> Result<Self, BotSpecError> {
+        match parse_human(name).or_else(|| parse_builtin(name)) {
+            Some(source) => Ok(Self {
+                name: name.to_string(),
+                source: source?,
+                protocol: None,
+            }),
+            None => Ok(Self::new(name)),
+        }
+    }
+
+    /// A person playing in the terminal, without a time limit unless one is given
+    pub fn human(time_limit: Option<u64>) -> Self {
+        Self {
+            name: "human".to_string(),
+            source: BotSource::Human { time_limit },
+            protocol: None,
+        }
+    }
+
+    /// A bot written in Rust that plays in the runner instead of in a process of its own. `new`
+    /// is called to create the bot for every game.
+    pub fn in_process<S: Bot + 'static>(
+        name: impl Into<String>,
+        new: impl Fn() -> S + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            source: BotSource::InProcess(BotFactory::new(new)),
+            protocol: None,
+        }
+    }
+
+    /// How long the bot may take for its moves. Humans only play by the time limits of the game
+    /// if they are given a time limit of their own.
+    pub(crate) fn move_limit(&self) -> MoveLimit {
+        match self.source {
+            BotSource::Human {
+                time_limit: Some(time_limit),
+            } => MoveLimit::Fixed(time_limit),
+            BotSource::Human { time_limit: None } => MoveLimit::Unlimited,
+            _ => MoveLimit::Game,
+        }
+    }
+
+    /// Command that starts the bot, if it is started as a process
+    pub(crate) fn command(&self, launchers: &Launchers) -> io::Result<Command> {
+        match &self.source {
+            BotSource::Script(script) => {
+                // interpreters start fine if the script is missing, and only fail once they try
+                // to load it
+                if !Path::new(script).is_file() {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+                }
+                launchers.command(script)
+            }
+            BotSource::Command { args, cwd } => {
+                let (program, args) = args
+                    .split_first()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+                let mut command = Command::new(program);
+                command.args(args);
+                if let Some(cwd) = cwd {
+                    command.current_dir(cwd);
+                }
+                Ok(command)
+            }
+            BotSource::Tcp(_)
+            | BotSource::Unix(_)
+            | BotSource::Lobby(_)
+            | BotSource::Human { .. }
+            | BotSource::InProcess(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "bot is not started as a process",
+            )),
+        }
+    }
+}
+
+impl From<&str> for BotSpec {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for BotSpec {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+/// Source of a bot given by a socket address, `None` if `address` has no socket scheme
+fn parse_address(address: &str) -> Option<BotSource> {
+    if let Some(address) = address.strip_prefix("tcp://") {
+        Some(BotSource::Tcp(address.to_string()))
+    } else {
+        let path = address.strip_prefix("unix://")?;
+        Some(BotSource::Unix(PathBuf::from(path)))
+    }
+}
+
+/// Source of a human given as `human` or `human:{time limit}`, `None` if `name` is neither
+fn parse_human(name: &str) -> Option<Result<BotSource, BotSpecError>> {
+    let time_limit = match name.split_once(':') {
+        None if name == "human" => None,
+        Some(("human", time_limit)) => Some(time_limit),
+        _ => return None,
+    };
+    Some(parse_time_limit(time_limit).map(|time_limit| BotSource::Human { time_limit }))
+}
+
+/// Source of a built-in bot given as `builtin:{name}`, `None` if `name` is not of that form
+fn parse_builtin(name: &str) -> Option<Result<BotSource, BotSpecError>> {
+    let builtin = name.strip_prefix("builtin:")?;
+    Some(find_builtin(builtin))
+}
+
+fn find_builtin(name: &str) -> Result<BotSource, BotSpecError> {
+    builtin_bot(name.trim())
+        .map(BotSource::InProcess)
+        .ok_or_else(|| BotSpecError::UnknownBuiltin(name.to_string()))
+}
+
+fn parse_protocol(version: &str) -> Result<u32, BotSpecError> {
+    match version.parse() {
+        Ok(version) if version == 1 || version == PROTOCOL_VERSION => Ok(version),
+        _ => Err(BotSpecError::InvalidProtocol(version.to_string())),
+    }
+}
+
+fn parse_time_limit(time_limit: Option<&str>) -> Result<Option<u64>, BotSpecError> {
+    time_limit
+        .map(|time_limit| match time_limit.trim().parse() {
+            Ok(time_limit) if time_limit > 0 => Ok(time_limit),
+            _ => Err(BotSpecError::InvalidTimeLimit(time_limit.to_string())),
+        })
+        .transpose()
+}
+
+/// Parses a bot spec: `;`-separated `key=value` pairs, e.g.
+/// `name=deep3;cmd=./mybot --depth 3;cwd=bots/`. One of `cmd`, `script` and `addr` has to be
+/// given. `cmd` is split on whitespace into the program and its arguments, and is run in `cwd` if
+/// given; a relative program is looked up from there. `script` is started like a script given by
+/// file name, and `addr` is the address of a socket the bot listens on. `human` makes a person
+/// play in the terminal, optionally with a time limit in milliseconds, e.g. `name=me;human=5000`,
+/// and `builtin` selects a built-in bot by name. The name defaults to the command, script or
+/// address, `human` or `builtin:{name}`. Anything that does not start with one of the keys is
+/// taken to be a bot name as accepted by `BotSpec::new`, except that an invalid time limit for a
+/// human and an unknown built-in bot are errors.
+impl std::str::FromStr for BotSpec {
+    type Err = BotSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let is_spec = [
+            "name=",
+            "cmd=",
+            "script=",
+            "addr=",
+            "cwd=",
+            "human=",
+            "builtin=",
+            "protocol=",
+        ]
+        .iter()
+        .any(|key| s.starts_with(key));
+        if !is_spec {
+            return Self::parse_name(s);
+        }
+
+        let (mut name, mut command, mut script, mut address, mut cwd) =
+            (None, None, None, None, None);
+        let (mut human, mut builtin, mut protocol) = (None, None, None);
+        for field in s.split(';').filter(|field| !field.trim().is_empty()) {
+            let (key, value) = field.split_once('=').unwrap_or((field, ""));
+            let value = Some(value.trim()).filter(|value| !value.is_empty());
+            match key.trim() {
+                "name" => name = value,
+                "cmd" => command = value,
+                "script" => script = value,
+                "addr" => address = value,
+                "cwd" => cwd = value,
+                "human" => human = Some(value),
+                "builtin" => builtin = Some(value.unwrap_or_default()),
+                "protocol" => protocol = Some(value.unwrap_or_default()),
+                other => return Err(BotSpecError::UnknownKey(other.to_string())),
+            }
+        }
+        let sources = [
+            command.is_some(),
+            script.is_some(),
+            address.is_some(),
+            human.is_some(),
+            builtin.is_some(),
+        ];
+        match sources.iter().filter(|given| **given).count() {
+            0 => return Err(BotSpecError::MissingCommand),
+            1 => {}
+            _ => return Err(BotSpecError::MultipleSources),
+        }
+        if cwd.is_some() && command.is_none() {
+            return Err(BotSpecError::CwdWithoutCommand);
+        }
+        let source = if let Some(command) = command {
+            BotSource::Command {
+                args: command.split_whitespace().map(str::to_string).collect(),
+                cwd: cwd.map(PathBuf::from),
+            }
+        } else if let Some(script) = script {
+            BotSource::Script(script.to_string())
+        } else if let Some(address) = address {
+            parse_address(address)
+                .ok_or_else(|| BotSpecError::InvalidAddress(address.to_string()))?
+        } else if let Some(time_limit) = human {
+            BotSource::Human {
+                time_limit: parse_time_limit(time_limit)?,
+            }
+        } else {
+            find_builtin(builtin.unwrap_or_default())?
+        };
+        let default_name = match (human, builtin) {
+            (Some(_), _) => Some("human".to_string()),
+            (_, Some(builtin)) => Some(format!("builtin:{builtin}")),
+            _ => None,
+        };
+        Ok(Self {
+            name: name
+                .or(command)
+                .or(script)
+                .or(address)
+                .map(str::to_string)
+                .or(default_name)
+                .unwrap_or_default(),
+            source,
+            protocol: protocol.map(parse_protocol).transpose()?,
+        })
+    }
+}
+
+/// Makes the names of the bots unique by numbering bots that share a name, e.g. when a script
+/// plays against itself: `randommover.py#1` and `randommover.py#2`
+pub(crate) fn make_names_unique(bots: &mut [BotSpec]) {
+    let names = bots.iter().map(|bot| bot.name.clone()).collect::<Vec<_>>();
+    for (player, bot) in bots.iter_mut().enumerate() {
+        if names.iter().filter(|name| **name == bot.name).count() > 1 {
+            let number = names[..player]
+                .iter()
+                .filter(|name| **name == bot.name)
+                .count()
+                + 1;
+            bot.name = format!("{}#{number}", bot.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        assert_eq!(
+            "name=deep3;cmd=./mybot --depth 3;cwd=bots/".parse(),
+            Ok(BotSpec {
+                name: "deep3".to_string(),
+                source: BotSource::Command {
+                    args: vec!["./mybot".into(), "--depth".into(), "3".into()],
+                    cwd: Some(PathBuf::from("bots/")),
+                },
+                protocol: None,
+            })
+        );
+        assert_eq!(
+            "script=bots/random.py".parse(),
+            Ok(BotSpec::script("bots/random.py"))
+        );
+        assert_eq!("my;bot.py".parse(), Ok(BotSpec::script("my;bot.py")));
+        assert_eq!(
+            "name=remote;addr=tcp://127.0.0.1:9000".parse(),
+            Ok(BotSpec {
+                name: "remote".to_string(),
+                source: BotSource::Tcp("127.0.0.1:9000".to_string()),
+                protocol: None,
+            })
+        );
+        assert_eq!(
+            BotSpec::new("unix:///tmp/bot.sock").source,
+            BotSource::Unix(PathBuf::from("/tmp/bot.sock"))
+        );
+        let bot: BotSpec = "cmd=./mybot --depth 5".parse().unwrap();
+        assert_eq!(bot.name, "./mybot --depth 5");
+        let bot: BotSpec = "script=old.py;protocol=1".parse().unwrap();
+        assert_eq!(bot.protocol, Some(1));
+        assert_eq!(BotSpec::new("old.py").protocol, None);
+    }
+
+    #[test]
+    fn humans() {
+        assert_eq!("human".parse(), Ok(BotSpec::human(None)));
+        let bot: BotSpec = "human:5000".parse().unwrap();
+        assert_eq!(
+            bot.source,
+            BotSource::Human {
+                time_limit: Some(5000)
+            }
+        );
+        assert_eq!(bot.move_limit(), MoveLimit::Fixed(5000));
+        assert_eq!(BotSpec::new("human").move_limit(), MoveLimit::Unlimited);
+        assert_eq!(
+            "name=me;human=300".parse(),
+            Ok(BotSpec {
+                name: "me".to_string(),
+                source: BotSource::Human {
+                    time_limit: Some(300)
+                },
+                protocol: None,
+            })
+        );
+        assert_eq!("name=me;human".parse::<BotSpec>().unwrap().name, "me");
+        assert_eq!(
+            "human:soon".parse::<BotSpec>(),
+            Err(BotSpecError::InvalidTimeLimit("soon".to_string()))
+        );
+        assert_eq!(
+            "human=0".parse::<BotSpec>(),
+            Err(BotSpecError::InvalidTimeLimit("0".to_string()))
+        );
+        assert_eq!(
+            "script=a.py;human".parse::<BotSpec>(),
+            Err(BotSpecError::MultipleSources)
+        );
+        // only the exact name, other names are scripts
+        assert_eq!(BotSpec::new("humans.py"), BotSpec::script("humans.py"));
+    }
+
+    #[test]
+    fn builtins() {
+        let bot = BotSpec::new("builtin:random");
+        assert_eq!(bot.name, "builtin:random");
+        assert!(matches!(bot.source, BotSource::InProcess(_)));
+        assert_eq!(bot.move_limit(), MoveLimit::Game);
+        let bot: BotSpec = "name=walker;builtin=unidirectional".parse().unwrap();
+        assert_eq!(bot.name, "walker");
+        assert!(matches!(bot.source, BotSource::InProcess(_)));
+        let bot: BotSpec = "builtin=random".parse().unwrap();
+        assert_eq!(bot.name, "builtin:random");
+        assert_eq!(
+            "builtin:smart".parse::<BotSpec>(),
+            Err(BotSpecError::UnknownBuiltin("smart".to_string()))
+        );
+        assert_eq!(
+            "builtin=random;human".parse::<BotSpec>(),
+            Err(BotSpecError::MultipleSources)
+        );
+    }
+
+    #[test]
+    fn invalid_specs() {
+        assert_eq!(
+            "name=deep3".parse::<BotSpec>(),
+            Err(BotSpecError::MissingCommand)
+        );
+        assert_eq!(
+            "cmd=./a;script=b.py".parse::<BotSpec>(),
+            Err(BotSpecError::MultipleSources)
+        );
+        assert_eq!(
+            "addr=127.0.0.1:9000".parse::<BotSpec>(),
+            Err(BotSpecError::InvalidAddress("127.0.0.1:9000".to_string()))
+        );
+        assert_eq!(
+            "script=b.py;cwd=bots".parse::<BotSpec>(),
+            Err(BotSpecError::CwdWithoutCommand)
+        );
+        assert_eq!(
+            "cmd=./a;protocol=3".parse::<BotSpec>(),
+            Err(BotSpecError::InvalidProtocol("3".to_string()))
+        );
+        assert_eq!(
+            "cmd=./a;depth=3".parse::<BotSpec>(),
+            Err(BotSpecError::UnknownKey("depth".to_string()))
+        );
+    }
+
+    #[test]
+    fn unique_names() {
+        let mut bots = ["a.py", "b.py", "a.py"].map(BotSpec::script);
+        make_names_unique(&mut bots);
+        let names = bots.map(|bot| bot.name);
+        assert_eq!(names, ["a.py#1", "b.py", "a.py#2"]);
+    }
+}
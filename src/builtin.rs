@@ -0,0 +1,184 @@
+use rand::seq::SliceRandom;
+
+use crate::game::{Direction, TorusSnakeGame};
+use crate::rules::{GameRules, GameSetup};
+use crate::strategy::{Bot, BotFactory};
+
+/// Names of the built-in bots, which are selected as `builtin:{name}`
+pub const BUILTIN_BOTS: [&str; 2] = ["random", "unidirectional"];
+
+/// Factory of the built-in bot with the given name, see `BUILTIN_BOTS`
+pub fn builtin_bot(name: &str) -> Option<BotFactory> {
+    match name {
+        "random" => Some(BotFactory::new(RandomMover::default)),
+        "unidirectional" => Some(BotFactory::new(UnidirectionalMover::default)),
+        _ => None,
+    }
+}
+
+/// The game as seen by a built-in bot. Like the example scripts, it does not model the classic
+/// rules, so cells left behind by tails stay occupied.
+struct Board {
+    game: TorusSnakeGame,
+    player: usize,
+}
+
+impl Board {
+    fn new(setup: &GameSetup, player: usize) -> Self {
+        Self {
+            game: TorusSnakeGame::new(setup),
+            player,
+        }
+    }
+
+    /// Whether moving in the direction does not lose right away
+    fn is_safe(&self, direction: Direction) -> bool {
+        let head = self.game.head_position(self.player);
+        self.game
+            .shift_coords(head, direction)
+            .is_some_and(|pos| self.game.is_free(pos))
+    }
+
+    /// Moves in the first safe direction, or in the last direction if none is safe
+    fn move_first_safe(&mut self, directions: &[Direction]) -> Direction {
+        let direction = directions
+            .iter()
+            .copied()
+            .find(|direction| self.is_safe(*direction))
+            .unwrap_or(directions[directions.len() - 1]);
+        self.game.apply_move(self.player, direction);
+        direction
+    }
+}
+
+/// Moves in a random direction that does not lose right away, if there is one. A port of
+/// `randommover.py`.
+#[derive(Default)]
+pub struct RandomMover {
+    board: Option<Board>,
+}
+
+impl Bot for RandomMover {
+    fn setup(&mut self, setup: &GameSetup, player: usize) {
+        self.board = Some(Board::new(setup, player));
+    }
+
+    fn observe_move(&mut self, player: usize, direction: Direction) {
+        if let Some(board) = &mut self.board {
+            board.game.apply_move(player, direction);
+        }
+    }
+
+    fn observe_out(&mut self, player: usize) {
+        if let Some(board) = &mut self.board {
+            board.game.eliminate(player);
+        }
+    }
+
+    fn choose_move(&mut self, _remaining_time: Option<u64>) -> Direction {
+        let mut directions = [
+            Direction::North,
+            Direction::South,
+            Direction::East,
+            Direction::West,
+        ];
+        directions.shuffle(&mut rand::thread_rng());
+        match &mut self.board {
+            Some(board) => board.move_first_safe(&directions),
+            None => directions[0],
+        }
+    }
+}
+
+/// Moves north whenever it can, otherwise east, south or west, in that order. A port of
+/// `unidirectionalmover.rs`.
+#[derive(Default)]
+pub struct UnidirectionalMover {
+    board: Option<Board>,
+}
+
+impl Bot for UnidirectionalMover {
+    fn setup(&mut self, setup: &GameSetup, player: usize) {
+        self.board = Some(Board::new(setup, player));
+    }
+
+    fn observe_move(&mut self, player: usize, direction: Direction) {
+        if let Some(board) = &mut self.board {
+            board.game.apply_move(player, direction);
+        }
+    }
+
+    fn observe_out(&mut self, player: usize) {
+        if let Some(board) = &mut self.board {
+            board.game.eliminate(player);
+        }
+    }
+
+    fn choose_move(&mut self, _remaining_time: Option<u64>) -> Direction {
+        let directions = [
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ];
+        match &mut self.board {
+            Some(board) => board.move_first_safe(&directions),
+            None => Direction::North,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::running::PlayerResult;
+    use crate::Runner;
+
+    fn setup(width: usize, height: usize, starting_positions: Vec<(usize, usize)>) -> GameSetup {
+        GameSetup {
+            width,
+            height,
+            starting_positions,
+            topology: crate::Topology::Box,
+            walls: Vec::new(),
+            rules: crate::Ruleset::Tron,
+            max_turns: None,
+        }
+    }
+
+    #[test]
+    fn unidirectional() {
+        let mut bot = UnidirectionalMover::default();
+        bot.setup(&setup(3, 3, vec![(1, 1), (1, 0)]), 0);
+        // player 1 is in the way
+        assert_eq!(bot.choose_move(None), Direction::East);
+        // now player 1 and the edge of the board are
+        bot.observe_move(1, Direction::East);
+        assert_eq!(bot.choose_move(None), Direction::South);
+    }
+
+    #[test]
+    fn random_avoids_losing() {
+        for _ in 0..20 {
+            let mut bot = RandomMover::default();
+            // only moving south does not leave the board or crash into player 1
+            bot.setup(&setup(2, 2, vec![(0, 0), (1, 0)]), 0);
+            assert_eq!(bot.choose_move(None), Direction::South);
+        }
+    }
+
+    #[test]
+    fn builtin_game() {
+        let result = Runner::new(["builtin:random", "builtin:unidirectional"])
+            .size(6, 6)
+            .play_game()
+            .unwrap();
+        for player_result in result.player_results {
+            assert!(matches!(
+                player_result,
+                PlayerResult::Winner | PlayerResult::Loser(crate::running::LossReason::LosingMove)
+            ));
+        }
+        assert!(builtin_bot("smart").is_none());
+    }
+}
@@ -0,0 +1,311 @@
+use colored::Colorize;
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::collections::VecDeque;
+
+use crate::game::{Direction, TorusSnakeGame};
+use crate::parse_instruction::Instruction;
+use crate::rules::{
+    render_board, Cell, GameRules, GameSetup, GameState, MoveOutcome, Ruleset,
+    DEFAULT_CLASSIC_LENGTH,
+};
+
+/// Number of food items on the board at any time
+const FOOD_ITEMS: usize = 1;
+
+/// The classic game of snake: snakes grow until they reach a fixed length, after which their tail
+/// vacates a cell for every move. Eating food makes a snake one cell longer. Dead snakes remain on
+/// the board.
+pub struct ClassicSnakeGame {
+    game: TorusSnakeGame, // keeps track of the board, with cells being cleared as tails move
+    length: usize,
+    bodies: Vec<VecDeque<(usize, usize)>>, // cells occupied by each snake, from tail to head
+    food_eaten: Vec<usize>,
+    food: Vec<(usize, usize)>,
+}
+
+impl ClassicSnakeGame {
+    /// Number of cells occupied by the snake of the player
+    pub fn snake_length(&self, player: usize) -> usize {
+        self.bodies[player].len()
+    }
+
+    pub fn has_food(&self, pos: (usize, usize)) -> bool {
+        self.food.contains(&pos)
+    }
+
+    pub fn place_food(&mut self, pos: (usize, usize)) {
+        self.food.push(pos);
+    }
+
+    /// The tail cell the player vacates when moving onto `target`. A snake that has reached its
+    /// full length and does not eat moves its tail along.
+    fn vacated_tail(&self, player: usize, target: (usize, usize)) -> Option<(usize, usize)> {
+        let full_length = self.length + self.food_eaten[player];
+        if self.has_food(target) || self.bodies[player].len() < full_length {
+            return None;
+        }
+        self.bodies[player].front().copied()
+    }
+
+    fn target(&self, player: usize, direction: Direction) -> Option<(usize, usize)> {
+        self.game
+            .shift_coords(self.game.head_position(player), direction)
+    }
+
+    /// Updates the body of a player whose head has just moved onto `pos`, eating any food there
+    fn grow_head(&mut self, player: usize, pos: (usize, usize)) {
+        self.bodies[player].push_back(pos);
+        if let Some(idx) = self.food.iter().position(|food| *food == pos) {
+            self.food.remove(idx);
+            self.food_eaten[player] += 1;
+        }
+    }
+
+    pub fn display_cell(&self, pos: (usize, usize)) -> String {
+        if self.has_food(pos) {
+            format!("{}", "*".yellow())
+        } else {
+            self.game.display_cell(pos)
+        }
+    }
+}
+
+impl GameRules for ClassicSnakeGame {
+    fn new(setup: &GameSetup) -> Self {
+        let length = match setup.rules {
+            Ruleset::Classic { length } => length,
+            Ruleset::Tron => DEFAULT_CLASSIC_LENGTH,
+        };
+        let n_players = setup.starting_positions.len();
+        Self {
+            game: TorusSnakeGame::new(setup),
+            length,
+            bodies: setup
+                .starting_positions
+                .iter()
+                .map(|pos| VecDeque::from([*pos]))
+                .collect(),
+            food_eaten: vec![0; n_players],
+            food: Vec::new(),
+        }
+    }
+
+    fn setup_string(&self) -> String {
+        format!(
+            "{}\nrules:{}",
+            self.game.setup_string(),
+            Ruleset::Classic {
+                length: self.length
+            }
+        )
+    }
+
+    fn apply_move(&mut self, player: usize, direction: Direction) -> MoveOutcome {
+        let tail = self
+            .target(player, direction)
+            .and_then(|pos| self.vacated_tail(player, pos));
+        if let Some(tail) = tail {
+            self.game.clear(tail); // so moving onto the cell our own tail vacates is allowed
+        }
+
+        let outcome = self.game.apply_move(player, direction);
+        match (outcome, tail) {
+            (MoveOutcome::Moved, _) => {
+                if tail.is_some() {
+                    self.bodies[player].pop_front();
+                }
+                self.grow_head(player, self.game.head_position(player));
+            }
+            (_, Some(tail)) => self.game.set_player(tail, player), // snake does not move after all
+            (_, None) => {}
+        }
+        outcome
+    }
+
+    /// Tails vacate their cells before any head moves, so a snake can move onto a cell another
+    /// snake's tail leaves in the same turn. A snake whose move loses keeps its tail, like in
+    /// `apply_move`, unless another snake moved onto it. Otherwise the same as the default rules.
+    fn apply_simultaneous_moves(&mut self, moves: &[(usize, Direction)]) -> Vec<MoveOutcome> {
+        let tails = moves
+            .iter()
+            .map(|&(player, direction)| {
+                let tail = self
+                    .target(player, direction)
+                    .and_then(|pos| self.vacated_tail(player, pos))?;
+                self.game.clear(tail);
+                self.bodies[player].pop_front();
+                Some(tail)
+            })
+            .collect_vec();
+
+        let outcomes = self.game.apply_simultaneous_moves(moves);
+        for ((&(player, _), outcome), tail) in moves.iter().zip(&outcomes).zip(tails) {
+            if !outcome.is_loss() {
+                self.grow_head(player, self.game.head_position(player));
+            } else if let Some(tail) = tail.filter(|tail| self.game.is_free(*tail)) {
+                self.game.set_player(tail, player);
+                self.bodies[player].push_front(tail);
+            }
+        }
+        outcomes
+    }
+
+    fn eliminate(&mut self, player: usize) {
+        self.game.eliminate(player);
+    }
+
+    fn end_turn(&mut self) {
+        self.game.end_turn();
+    }
+
+    /// Spawns food on random free cells until there is enough food on the board
+    fn spawn_items(&mut self) -> Vec<Instruction> {
+        let mut spawned = Vec::new();
+        while self.food.len() < FOOD_ITEMS {
+            let free_cells = (0..self.game.height())
+                .flat_map(|y| (0..self.game.width()).map(move |x| (x, y)))
+                .filter(|pos| self.game.is_free(*pos) && !self.has_food(*pos))
+                .collect_vec();
+            let Some(&position) = free_cells.choose(&mut thread_rng()) else {
+                break; // board is full
+            };
+            self.place_food(position);
+            spawned.push(Instruction::Food { position });
+        }
+        spawned
+    }
+
+    fn apply_event(&mut self, event: &Instruction) {
+        if let Instruction::Food { position } = event {
+            self.place_food(*position);
+        }
+    }
+
+    fn is_alive(&self, player: usize) -> bool {
+        self.game.is_alive(player)
+    }
+
+    fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+
+    /// The last remaining player, or the longest remaining snake when the turn limit is reached.
+    /// If several remaining snakes are equally long, nobody wins.
+    fn winner(&self) -> Option<usize> {
+        if !self.game.turn_limit_reached() {
+            return self.game.winner();
+        }
+        let alive_players = self.game.alive_players();
+        let longest = alive_players
+            .iter()
+            .max_set_by_key(|player| self.snake_length(**player));
+        match longest[..] {
+            [winner] => Some(*winner),
+            _ => None,
+        }
+    }
+
+    fn state(&self) -> GameState {
+        let mut state = self.game.state();
+        for &(x, y) in &self.food {
+            state.board[y][x] = Cell::Food;
+        }
+        state
+    }
+}
+
+impl std::fmt::Display for ClassicSnakeGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            render_board(self.game.width(), self.game.height(), |pos| {
+                self.display_cell(pos)
+            })
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Topology;
+    use Direction::*;
+
+    fn setup(rules: Ruleset) -> GameSetup {
+        GameSetup {
+            width: 5,
+            height: 5,
+            starting_positions: vec![(0, 0), (3, 3)],
+            topology: Topology::Torus,
+            walls: Vec::new(),
+            rules,
+            max_turns: None,
+        }
+    }
+
+    fn new_game(length: usize) -> ClassicSnakeGame {
+        ClassicSnakeGame::new(&setup(Ruleset::Classic { length }))
+    }
+
+    #[test]
+    fn setup_of_other_rules() {
+        let game = ClassicSnakeGame::new(&setup(Ruleset::Tron));
+        assert!(game
+            .setup_string()
+            .ends_with(&format!("rules:classic:{DEFAULT_CLASSIC_LENGTH}")));
+    }
+
+    #[test]
+    fn tail_moves() {
+        let mut game = new_game(4);
+        assert_eq!(game.apply_move(0, East), MoveOutcome::Moved);
+        assert_eq!(game.apply_move(0, South), MoveOutcome::Moved);
+        assert_eq!(game.apply_move(0, West), MoveOutcome::Moved);
+        // moving onto the cell our own tail vacates is allowed
+        assert_eq!(game.apply_move(0, North), MoveOutcome::Moved);
+        assert_eq!(game.apply_move(0, East), MoveOutcome::Moved);
+        assert_eq!(game.apply_move(0, West), MoveOutcome::Crashed);
+        assert_eq!(game.snake_length(0), 4);
+    }
+
+    #[test]
+    fn losing_simultaneous_move() {
+        let mut game = new_game(3);
+        game.apply_simultaneous_moves(&[(0, East), (1, East)]);
+        game.apply_simultaneous_moves(&[(0, East), (1, East)]);
+        // player 0 turns back into its own body, which vacates its tail only if the move succeeds
+        let outcomes = game.apply_simultaneous_moves(&[(0, West), (1, South)]);
+        assert_eq!(outcomes, [MoveOutcome::Crashed, MoveOutcome::Moved]);
+        // player 0 keeps its tail, like when moving one after another
+        assert_eq!(game.snake_length(0), 3);
+        assert_eq!(game.state().board[0][..3], [Cell::Snake(0); 3]);
+        assert_eq!(game.snake_length(1), 3);
+        assert_eq!(game.state().board[3][3], Cell::Free);
+    }
+
+    #[test]
+    fn food_grows() {
+        let mut game = new_game(1);
+        game.place_food((1, 0));
+        game.apply_move(0, East);
+        assert!(!game.has_food((1, 0)));
+        game.apply_move(0, East);
+        assert_eq!(game.snake_length(0), 2);
+        assert_eq!(game.spawn_items().len(), 1);
+    }
+
+    #[test]
+    fn state_with_food() {
+        let mut game = new_game(1);
+        game.place_food((4, 4));
+        game.apply_move(0, East);
+        let state = game.state();
+        // the tail has moved on
+        assert_eq!(state.board[0][..2], [Cell::Free, Cell::Snake(0)]);
+        assert_eq!(state.board[4][4], Cell::Food);
+        assert_eq!(state.heads, [(1, 0), (3, 3)]);
+    }
+}
@@ -0,0 +1,396 @@
+use itertools::Itertools;
+use std::time::{Duration, Instant};
+
+use crate::running::GameSettings;
+
+/// When limiting CPU time, a move may take this many times the time limit in wall-clock time
+/// before the player times out anyway, e.g. because it is waiting instead of computing
+const WALL_CLOCK_BACKSTOP: u32 = 10;
+
+/// How often the CPU time of players is checked while waiting for their moves
+const CPU_TIME_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Chess clock style time control: every player starts with a bank of `initial` milliseconds,
+/// which is used up while the player thinks about its moves. `increment` milliseconds are added
+/// to the bank after every move.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeControl {
+    pub initial: u64,
+    pub increment: u64,
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Invalid time control, expected {{initial}}+{{increment}} in milliseconds, e.g. 5000+50")]
+pub struct InvalidTimeControl;
+
+impl std::str::FromStr for TimeControl {
+    type Err = InvalidTimeControl;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (initial, increment) = s.trim().split_once('+').unwrap_or((s.trim(), "0"));
+        let initial = initial.parse().map_err(|_| InvalidTimeControl)?;
+        let increment = increment.parse().map_err(|_| InvalidTimeControl)?;
+        if initial == 0 {
+            return Err(InvalidTimeControl);
+        }
+        Ok(Self { initial, increment })
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}", self.initial, self.increment)
+    }
+}
+
+/// CPU time (user and system) used so far by a process, read from `/proc/<pid>/stat`. `None` if
+/// it cannot be read, e.g. because the process has been reaped
+#[cfg(target_os = "linux")]
+pub(crate) fn cpu_time(pid: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let (_, ticks) = parse_stat(&stat, false)?;
+    Some(ticks_to_duration(ticks, ticks_per_second()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn cpu_time(_pid: u32) -> Option<Duration> {
+    None
+}
+
+/// CPU time used so far by the process group `pgid`: by the processes in it, including those that
+/// exited but were not waited for yet, and by the children they waited for. A bot is the leader
+/// of its own process group, so this includes the workers it starts. `None` if no process of the
+/// group is left.
+#[cfg(target_os = "linux")]
+pub(crate) fn group_cpu_time(pgid: u32) -> Option<Duration> {
+    let mut ticks = None;
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let name = entry.file_name();
+        let is_process = name
+            .to_str()
+            .is_some_and(|name| !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_digit()));
+        if !is_process {
+            continue;
+        }
+        // a process may exit while we look at the others
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        match parse_stat(&stat, true) {
+            Some((group, process_ticks)) if group == pgid => {
+                *ticks.get_or_insert(0) += process_ticks;
+            }
+            _ => {}
+        }
+    }
+    Some(ticks_to_duration(ticks?, ticks_per_second()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn group_cpu_time(_pgid: u32) -> Option<Duration> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn ticks_per_second() -> Option<u64> {
+    // SAFETY: sysconf only reads a configuration value
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    u64::try_from(ticks_per_second).ok()
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn ticks_to_duration(ticks: u64, ticks_per_second: u64) -> Duration {
+    Duration::from_millis(ticks * 1000 / ticks_per_second.max(1))
+}
+
+/// Process group and CPU time in clock ticks from a `/proc/<pid>/stat` file: the sum of the
+/// `utime` and `stime` fields, and with `children` also of `cutime` and `cstime`, the time of the
+/// children the process waited for
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str, children: bool) -> Option<(u32, u64)> {
+    // the name of the process can contain spaces and parentheses, so we start after it. The
+    // state of the process is the first field after the name, the process group the 3rd, utime
+    // and stime are the 12th and 13th, cutime and cstime the 14th and 15th
+    let fields = stat.rsplit_once(')')?.1.split_whitespace().collect_vec();
+    let group = fields.get(2)?.parse().ok()?;
+    let times = if children { 11..15 } else { 11..13 };
+    let ticks = times
+        .map(|field| fields.get(field)?.parse::<u64>().ok())
+        .sum::<Option<u64>>()?;
+    Some((group, ticks))
+}
+
+/// How long a player may take for a move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MoveLimit {
+    /// The time limit or time control of the game
+    Game,
+    /// A fixed number of milliseconds for every move, also in the first turn
+    Fixed(u64),
+    Unlimited,
+}
+
+/// Time a player took for a move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MoveTime {
+    /// `None` if the CPU time of the player was not measured
+    pub cpu: Option<Duration>,
+    pub wall: Duration,
+}
+
+/// Times a single move of a player
+pub(crate) struct Stopwatch {
+    pid: Option<u32>, // of the bot, which leads its own process group
+    start: Instant,
+    start_cpu: Option<Duration>,  // only when limiting CPU time
+    time_limit: Option<Duration>, // `None` if the player may take as long as it likes
+    deadline: Option<Instant>,    // for wall-clock time
+}
+
+impl Stopwatch {
+    pub(crate) fn read(&self) -> MoveTime {
+        MoveTime {
+            cpu: self.start_cpu.and_then(|start_cpu| {
+                let cpu = group_cpu_time(self.pid?)?;
+                Some(cpu.saturating_sub(start_cpu))
+            }),
+            wall: self.start.elapsed(),
+        }
+    }
+
+    /// Whether the player has used up its time for the move
+    pub(crate) fn is_over(&self, time: &MoveTime) -> bool {
+        let Some(time_limit) = self.time_limit else {
+            return false;
+        };
+        self.deadline
+            .is_some_and(|deadline| Instant::now() > deadline)
+            || time.cpu.is_some_and(|cpu| cpu > time_limit)
+    }
+
+    /// When to check again whether the player is out of time, `None` if it never runs out
+    pub(crate) fn next_check(&self) -> Option<Instant> {
+        let deadline = self.deadline?;
+        if self.start_cpu.is_some() {
+            Some(deadline.min(Instant::now() + CPU_TIME_POLL_INTERVAL))
+        } else {
+            Some(deadline)
+        }
+    }
+}
+
+/// Keeps track of how long every player may take for its next move. Without a time control every
+/// move gets the same time limit, except for the moves in the first turn, which get 10x more time
+/// to allow for some setup. Players can be given a limit of their own instead, e.g. humans.
+pub(crate) struct Clock {
+    time_limit: u64,
+    time_control: Option<TimeControl>,
+    cpu_time: bool,
+    remaining: Vec<u64>, // time left in the bank of every player, only used with a time control
+    last_moves: Vec<Option<MoveTime>>,
+    move_limits: Vec<MoveLimit>,
+    first_turn: bool,
+}
+
+impl Clock {
+    pub(crate) fn new(settings: &GameSettings, n_players: usize) -> Self {
+        let time_control = settings.time_control;
+        Self {
+            time_limit: settings.time_limit,
+            time_control,
+            cpu_time: settings.cpu_time,
+            remaining: vec![time_control.map_or(0, |control| control.initial); n_players],
+            last_moves: vec![None; n_players],
+            move_limits: vec![MoveLimit::Game; n_players],
+            first_turn: true,
+        }
+    }
+
+    /// Starts timing a move of the player. When limiting CPU time, the wall-clock time is only
+    /// limited as a backstop, unless the CPU time of the player cannot be read.
+    pub(crate) fn start(&self, player: usize, pid: Option<u32>) -> Stopwatch {
+        let time_limit = self.time_limit(player).map(Duration::from_millis);
+        let start_cpu = pid.filter(|_| self.cpu_time).and_then(group_cpu_time);
+        let start = Instant::now();
+        let wall_clock_limit = match start_cpu {
+            Some(_) => time_limit.map(|limit| limit * WALL_CLOCK_BACKSTOP),
+            None => time_limit,
+        };
+        Stopwatch {
+            pid,
+            start,
+            start_cpu,
+            time_limit,
+            deadline: wall_clock_limit.and_then(|limit| start.checked_add(limit)),
+        }
+    }
+
+    /// Gives the player a limit of its own instead of the one of the game
+    pub(crate) fn set_move_limit(&mut self, player: usize, limit: MoveLimit) {
+        self.move_limits[player] = limit;
+    }
+
+    /// Time in milliseconds the player has for its next move, `None` if it is unlimited
+    pub(crate) fn time_limit(&self, player: usize) -> Option<u64> {
+        match (self.move_limits[player], self.time_control) {
+            (MoveLimit::Fixed(time_limit), _) => Some(time_limit),
+            (MoveLimit::Unlimited, _) => None,
+            (MoveLimit::Game, Some(_)) => Some(self.remaining[player]),
+            (MoveLimit::Game, None) if self.first_turn => Some(10 * self.time_limit),
+            (MoveLimit::Game, None) => Some(self.time_limit),
+        }
+    }
+
+    /// Time left in the bank of the player, if playing with a time control and the player has no
+    /// limit of its own
+    pub(crate) fn remaining(&self, player: usize) -> Option<u64> {
+        self.time_control
+            .filter(|_| self.move_limits[player] == MoveLimit::Game)
+            .map(|_| self.remaining[player])
+    }
+
+    /// Takes the time the player used for a move from its bank and adds the increment
+    pub(crate) fn charge(&mut self, player: usize, time: MoveTime) {
+        self.last_moves[player] = Some(time);
+        if let Some(control) = self.time_control {
+            let used = time.cpu.unwrap_or(time.wall);
+            let used = u64::try_from(used.as_millis()).unwrap_or(u64::MAX);
+            self.remaining[player] =
+                self.remaining[player].saturating_sub(used) + control.increment;
+        }
+    }
+
+    /// Empties the bank of a player that did not answer in time
+    pub(crate) fn flag(&mut self, player: usize, time: MoveTime) {
+        self.last_moves[player] = Some(time);
+        self.remaining[player] = 0;
+    }
+
+    /// CPU and wall-clock time in milliseconds the player took for its last move, if limiting CPU
+    /// time
+    pub(crate) fn last_move(&self, player: usize) -> Option<(u64, u64)> {
+        let time = self.last_moves[player].filter(|_| self.cpu_time)?;
+        let millis = |time: Duration| u64::try_from(time.as_millis()).unwrap_or(u64::MAX);
+        Some((millis(time.cpu.unwrap_or(time.wall)), millis(time.wall)))
+    }
+
+    pub(crate) fn end_turn(&mut self) {
+        self.first_turn = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Runner;
+
+    fn move_time(millis: u64) -> MoveTime {
+        MoveTime {
+            cpu: None,
+            wall: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "5000+50".parse(),
+            Ok(TimeControl {
+                initial: 5000,
+                increment: 50
+            })
+        );
+        assert_eq!(
+            "300".parse(),
+            Ok(TimeControl {
+                initial: 300,
+                increment: 0
+            })
+        );
+        assert_eq!("0+50".parse::<TimeControl>(), Err(InvalidTimeControl));
+        assert_eq!("5000+".parse::<TimeControl>(), Err(InvalidTimeControl));
+        assert_eq!("-5000+50".parse::<TimeControl>(), Err(InvalidTimeControl));
+        assert_eq!(
+            "5000+50".parse::<TimeControl>().unwrap().to_string(),
+            "5000+50"
+        );
+    }
+
+    #[test]
+    fn bank() {
+        let control = TimeControl {
+            initial: 1000,
+            increment: 50,
+        };
+        let mut clock = Clock::new(&Runner::new(["a"]).time_control(control).settings(), 2);
+        clock.charge(0, move_time(300));
+        assert_eq!(clock.time_limit(0), Some(750));
+        assert_eq!(clock.remaining(1), Some(1000));
+        clock.flag(1, move_time(1000));
+        assert_eq!(clock.time_limit(1), Some(0));
+    }
+
+    #[test]
+    fn fixed_time_limit() {
+        let mut clock = Clock::new(&Runner::new(["a"]).time_limit(100).settings(), 2);
+        assert_eq!(clock.time_limit(0), Some(1000));
+        clock.charge(0, move_time(300));
+        clock.end_turn();
+        assert_eq!(clock.time_limit(0), Some(100));
+        assert_eq!(clock.remaining(0), None);
+        assert_eq!(clock.last_move(0), None);
+    }
+
+    #[test]
+    fn own_move_limits() {
+        let control = TimeControl {
+            initial: 1000,
+            increment: 50,
+        };
+        let mut clock = Clock::new(&Runner::new(["a"]).time_control(control).settings(), 3);
+        clock.set_move_limit(1, MoveLimit::Fixed(5000));
+        clock.set_move_limit(2, MoveLimit::Unlimited);
+        clock.charge(1, move_time(300));
+        assert_eq!(clock.time_limit(1), Some(5000));
+        assert_eq!(clock.remaining(1), None);
+        assert_eq!(clock.time_limit(2), None);
+
+        let stopwatch = clock.start(2, None);
+        assert_eq!(stopwatch.next_check(), None);
+        assert!(!stopwatch.is_over(&move_time(u64::MAX)));
+    }
+
+    #[test]
+    fn cpu_time_from_stat() {
+        let stat = "4242 (my bot (v2)) S 1 4242 4242 0 -1 4194304 1234 0 0 0 37 5 0 0 20 0 1 0 \
+                    900 12345678 2000 18446744073709551615";
+        assert_eq!(parse_stat(stat, false), Some((4242, 42)));
+        assert_eq!(parse_stat(stat, true), Some((4242, 42)));
+        let stat = stat.replace(" 37 5 0 0 ", " 37 5 8 2 ");
+        assert_eq!(parse_stat(&stat, true), Some((4242, 52)));
+        assert_eq!(parse_stat("4242 (bot) S 1", false), None);
+        assert_eq!(ticks_to_duration(42, 100), Duration::from_millis(420));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cpu_time_of_workers() {
+        use std::os::unix::process::CommandExt;
+        // the bot only waits, while a worker it started computes
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "(while :; do :; done) & wait"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while group_cpu_time(pid).unwrap() < Duration::from_millis(200) {
+            assert!(Instant::now() < deadline);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(cpu_time(pid).unwrap() < Duration::from_millis(100));
+        // SAFETY: kill only sends a signal
+        unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        child.wait().unwrap();
+    }
+}
@@ -2,58 +2,95 @@ use colored::Colorize;
 use itertools::Itertools;
 use std::collections::HashSet;
 
+use crate::rules::{render_board, Cell, GameRules, GameSetup, GameState, MoveOutcome};
+use crate::topology::Topology;
+
+/// The default game: snakes never shrink, so every cell a snake has visited stays occupied
 pub struct TorusSnakeGame {
     board: Vec<Vec<Option<usize>>>,
+    walls: Vec<Vec<bool>>,
     height: usize,
     width: usize,
     head_positions: Vec<(usize, usize)>,
     alive_players: HashSet<usize>,
+    topology: Topology,
+    turn: usize,
+    max_turns: Option<usize>,
 }
 
 impl TorusSnakeGame {
-    pub fn new(width: usize, height: usize, starting_positions: Vec<(usize, usize)>) -> Self {
-        let mut board = vec![vec![None; width]; height];
-        for (player_num, (x, y)) in starting_positions.iter().enumerate() {
-            board[*y][*x] = Some(player_num);
-        }
-        let n_players = starting_positions.len();
-        Self {
-            board,
-            height,
-            width,
-            head_positions: starting_positions,
-            alive_players: (0..n_players).collect(),
-        }
+    /// Coordinates of the cell reached from `pos` by moving in `direction`, or `None` if that
+    /// leaves the board. What happens at the edges depends on the topology of the game.
+    pub fn shift_coords(
+        &self,
+        pos: (usize, usize),
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        self.topology
+            .shift_coords(pos, direction, self.width, self.height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    pub fn shift_coords(&self, (x, y): (usize, usize), direction: Direction) -> (usize, usize) {
-        let (dx, dy) = direction.coord_shift();
-        let new_x = ((x + self.width) as i32 + dx) as usize % self.width;
-        let new_y = ((y + self.height) as i32 + dy) as usize % self.height;
-        (new_x, new_y)
+    pub fn height(&self) -> usize {
+        self.height
     }
 
     pub fn get(&self, (x, y): (usize, usize)) -> Option<usize> {
         self.board[y][x]
     }
 
-    // fn clear(&mut self, (x, y): (usize, usize)) {
-    //     self.board[y][x] = None;
-    // }
+    pub fn is_wall(&self, (x, y): (usize, usize)) -> bool {
+        self.walls[y][x]
+    }
+
+    pub fn cell(&self, pos: (usize, usize)) -> Cell {
+        match self.get(pos) {
+            Some(player) => Cell::Snake(player),
+            None if self.is_wall(pos) => Cell::Wall,
+            None => Cell::Free,
+        }
+    }
+
+    /// Whether a snake can safely move onto the cell
+    pub fn is_free(&self, pos: (usize, usize)) -> bool {
+        self.get(pos).is_none() && !self.is_wall(pos)
+    }
+
+    pub fn head_position(&self, player: usize) -> (usize, usize) {
+        self.head_positions[player]
+    }
+
+    pub fn alive_players(&self) -> &HashSet<usize> {
+        &self.alive_players
+    }
+
+    pub fn turn_limit_reached(&self) -> bool {
+        self.max_turns
+            .is_some_and(|max_turns| self.turn >= max_turns)
+    }
+
+    pub(crate) fn clear(&mut self, (x, y): (usize, usize)) {
+        self.board[y][x] = None;
+    }
 
     pub fn set_player(&mut self, (x, y): (usize, usize), player: usize) {
         self.board[y][x] = Some(player);
     }
 
-    pub fn move_player(&mut self, player: usize, direction: Direction) -> bool {
-        let new_pos = self.shift_coords(self.head_positions[player], direction);
-        if self.get(new_pos).is_none() {
-            self.head_positions[player] = new_pos;
-            self.set_player(new_pos, player);
-            true
-        } else {
-            self.alive_players.remove(&player);
-            false
+    /// Moves the head of the player onto a cell, without checking whether that is allowed
+    pub(crate) fn place_head(&mut self, player: usize, pos: (usize, usize)) {
+        self.head_positions[player] = pos;
+        self.set_player(pos, player);
+    }
+
+    fn move_outcome(&self, player: usize, direction: Direction) -> MoveOutcome {
+        match self.shift_coords(self.head_positions[player], direction) {
+            None => MoveOutcome::LeftBoard,
+            Some(pos) if !self.is_free(pos) => MoveOutcome::Crashed,
+            Some(_) => MoveOutcome::Moved,
         }
     }
 
@@ -70,14 +107,42 @@ impl TorusSnakeGame {
                 disp = disp.red()
             }
             format!("{}", disp)
+        } else if self.is_wall(pos) {
+            "#".into()
         } else {
             "·".into()
         }
     }
+}
+
+impl GameRules for TorusSnakeGame {
+    fn new(setup: &GameSetup) -> Self {
+        let (width, height) = (setup.width, setup.height);
+        let mut board = vec![vec![None; width]; height];
+        for (player_num, (x, y)) in setup.starting_positions.iter().enumerate() {
+            board[*y][*x] = Some(player_num);
+        }
+        let mut walls = vec![vec![false; width]; height];
+        for (x, y) in &setup.walls {
+            walls[*y][*x] = true;
+        }
+        let n_players = setup.starting_positions.len();
+        Self {
+            board,
+            walls,
+            height,
+            width,
+            head_positions: setup.starting_positions.clone(),
+            alive_players: (0..n_players).collect(),
+            topology: setup.topology,
+            turn: 0,
+            max_turns: setup.max_turns,
+        }
+    }
 
     #[allow(unstable_name_collisions)] // intersperse will be added to std, but change is probably not breaking
-    pub fn setup_string(&self) -> String {
-        format!(
+    fn setup_string(&self) -> String {
+        let mut setup = format!(
             "{},{}\n{}\n{}",
             self.width,
             self.height,
@@ -87,26 +152,106 @@ impl TorusSnakeGame {
                 .map(|(x, y)| format!("{x},{y}"))
                 .intersperse("\n".to_owned())
                 .collect::<String>()
-        )
+        );
+        if self.topology != Topology::Torus {
+            setup.push_str(&format!("\ntopology:{}", self.topology));
+        }
+        let walls = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|pos| self.is_wall(*pos))
+            .map(|(x, y)| format!("{x},{y}"))
+            .join(" ");
+        if !walls.is_empty() {
+            setup.push_str(&format!("\nwalls:{walls}"));
+        }
+        setup
+    }
+
+    fn apply_move(&mut self, player: usize, direction: Direction) -> MoveOutcome {
+        let outcome = self.move_outcome(player, direction);
+        match self.shift_coords(self.head_positions[player], direction) {
+            Some(pos) if !outcome.is_loss() => self.place_head(player, pos),
+            _ => self.eliminate(player),
+        }
+        outcome
+    }
+
+    /// A player loses if the cell it moves into is already occupied or off the board, or if
+    /// another player moves into the same cell during this turn (head-on collision, in which case
+    /// both lose). Players that lose do not move.
+    fn apply_simultaneous_moves(&mut self, moves: &[(usize, Direction)]) -> Vec<MoveOutcome> {
+        let targets = moves
+            .iter()
+            .map(|&(player, direction)| self.shift_coords(self.head_positions[player], direction))
+            .collect_vec();
+
+        let outcomes = moves
+            .iter()
+            .zip(&targets)
+            .map(|(&(player, direction), target)| {
+                let head_on = moves
+                    .iter()
+                    .zip(&targets)
+                    .any(|(&(other, _), other_target)| other != player && other_target == target);
+                match self.move_outcome(player, direction) {
+                    MoveOutcome::Moved if head_on => MoveOutcome::HeadOn,
+                    outcome => outcome,
+                }
+            })
+            .collect_vec();
+
+        for ((&(player, _), target), outcome) in moves.iter().zip(targets).zip(&outcomes) {
+            match target {
+                Some(pos) if !outcome.is_loss() => self.place_head(player, pos),
+                _ => self.eliminate(player),
+            }
+        }
+        outcomes
+    }
+
+    fn eliminate(&mut self, player: usize) {
+        self.alive_players.remove(&player);
+    }
+
+    fn end_turn(&mut self) {
+        self.turn += 1;
+    }
+
+    fn is_alive(&self, player: usize) -> bool {
+        self.alive_players.contains(&player)
+    }
+
+    fn is_over(&self) -> bool {
+        self.alive_players.len() < 2 || self.turn_limit_reached()
+    }
+
+    /// The last remaining player. If several players are still alive when the turn limit is
+    /// reached, nobody wins.
+    fn winner(&self) -> Option<usize> {
+        match self.alive_players.iter().collect_vec()[..] {
+            [winner] => Some(*winner),
+            _ => None,
+        }
+    }
+
+    fn state(&self) -> GameState {
+        GameState {
+            turn: self.turn,
+            alive: self.alive_players.iter().copied().sorted().collect(),
+            heads: self.head_positions.clone(),
+            board: (0..self.height)
+                .map(|y| (0..self.width).map(|x| self.cell((x, y))).collect())
+                .collect(),
+        }
     }
 }
 
 impl std::fmt::Display for TorusSnakeGame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let horizontal_border = format!("+{}+", "-".to_owned().repeat(self.width));
         write!(
             f,
-            "{}\n{}{}",
-            horizontal_border.clone(),
-            (0..self.height)
-                .map(|y| format!(
-                    "|{}|\n",
-                    (0..self.width)
-                        .map(|x| self.display_cell((x, y)))
-                        .collect::<String>()
-                ))
-                .collect::<String>(),
-            horizontal_border
+            "{}",
+            render_board(self.width, self.height, |pos| self.display_cell(pos))
         )
     }
 }
@@ -120,7 +265,7 @@ pub enum Direction {
 }
 
 impl Direction {
-    fn coord_shift(&self) -> (i32, i32) {
+    pub(crate) fn coord_shift(&self) -> (i32, i32) {
         match self {
             Self::North => (0, -1),
             Self::South => (0, 1),
@@ -130,7 +275,7 @@ impl Direction {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, PartialEq, thiserror::Error)]
 #[error("Could not be parsed to direction")]
 pub struct InvalidDirection;
 
@@ -140,7 +285,7 @@ impl std::str::FromStr for Direction {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.trim().to_lowercase().as_str() {
             "n" | "north" => Ok(Self::North),
-            "s" | "south " => Ok(Self::South),
+            "s" | "south" => Ok(Self::South),
             "e" | "east" => Ok(Self::East),
             "w" | "west" => Ok(Self::West),
             _ => Err(InvalidDirection {}),
@@ -159,7 +304,104 @@ impl std::fmt::Display for Direction {
                 Self::East => "E",
                 Self::West => "W",
             }
-            .to_string()
         )
     }
 }
+
+/// Directions are written as in the line protocol, e.g. `"N"`
+impl serde::Serialize for Direction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Direction {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Ruleset;
+    use Direction::*;
+    use MoveOutcome::*;
+
+    fn new_game(starting_positions: Vec<(usize, usize)>, topology: Topology) -> TorusSnakeGame {
+        TorusSnakeGame::new(&GameSetup {
+            width: 5,
+            height: 5,
+            starting_positions,
+            topology,
+            walls: vec![(1, 4), (4, 4)],
+            rules: Ruleset::Tron,
+            max_turns: Some(3),
+        })
+    }
+
+    #[test]
+    fn simultaneous_head_on() {
+        let mut game = new_game(vec![(1, 2), (3, 2), (2, 0)], Topology::Torus);
+        let outcomes = game.apply_simultaneous_moves(&[(0, East), (1, West), (2, South)]);
+        assert_eq!(outcomes, vec![HeadOn, HeadOn, Moved]);
+        assert_eq!(game.get((2, 2)), None);
+        assert_eq!(game.get((2, 1)), Some(2));
+        assert!(game.is_over());
+        assert_eq!(game.winner(), Some(2));
+    }
+
+    #[test]
+    fn simultaneous_occupied() {
+        let mut game = new_game(vec![(1, 2), (2, 2)], Topology::Torus);
+        let outcomes = game.apply_simultaneous_moves(&[(0, East), (1, North)]);
+        assert_eq!(outcomes, vec![Crashed, Moved]);
+        assert_eq!(game.get((2, 1)), Some(1));
+    }
+
+    #[test]
+    fn off_the_board() {
+        let mut game = new_game(vec![(0, 2), (4, 2)], Topology::Box);
+        assert_eq!(game.apply_move(0, West), LeftBoard);
+        assert_eq!(game.apply_simultaneous_moves(&[(1, East)]), vec![LeftBoard]);
+        assert_eq!(game.get((0, 2)), Some(0));
+    }
+
+    #[test]
+    fn walls() {
+        let mut game = new_game(vec![(1, 0), (2, 2)], Topology::Torus);
+        assert_eq!(game.apply_move(0, North), Crashed);
+        assert!(game.setup_string().ends_with("\nwalls:1,4 4,4"));
+    }
+
+    #[test]
+    fn state() {
+        let mut game = new_game(vec![(0, 0), (2, 2)], Topology::Torus);
+        game.apply_move(0, South);
+        game.end_turn();
+        game.eliminate(1);
+        let state = game.state();
+        assert_eq!(state.turn, 1);
+        assert_eq!(state.alive, [0]);
+        assert_eq!(state.heads, [(0, 1), (2, 2)]);
+        assert_eq!(state.board[1][0], Cell::Snake(0));
+        assert_eq!(state.board[4][..2], [Cell::Free, Cell::Wall]);
+        assert_eq!(
+            state.to_string(),
+            "1:0:0,1 2,2:0,.,.,.,. 0,.,.,.,. .,.,1,.,. .,.,.,.,. .,#,.,.,#"
+        );
+    }
+
+    #[test]
+    fn turn_limit() {
+        let mut game = new_game(vec![(0, 0), (2, 2)], Topology::Torus);
+        for _ in 0..3 {
+            assert!(!game.is_over());
+            game.end_turn();
+        }
+        assert!(game.is_over());
+        assert_eq!(game.winner(), None);
+    }
+}
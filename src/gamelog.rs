@@ -0,0 +1,483 @@
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+use crate::clock::TimeControl;
+use crate::game::Direction;
+use crate::parse_instruction::{
+    parse_number, parse_player, parse_player_move, parse_usize_pair, Instruction,
+    InstructionParseError,
+};
+use crate::rules::{GameSetup, Ruleset};
+use crate::topology::Topology;
+
+/// A game as stored in a log file: the header that was sent to the players (without player id),
+/// followed by everything that happened during the game in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameLog {
+    /// Setup of the game. The turn limit is not logged, so `max_turns` is always `None`
+    pub setup: GameSetup,
+    /// Whether players moved simultaneously, in which case moves are logged per turn
+    pub simultaneous: bool,
+    /// Time control the game was played with, if any
+    pub time_control: Option<TimeControl>,
+    /// Display names of the players, ordered by player. Empty if the log has no names
+    pub names: Vec<String>,
+    pub entries: Vec<LogEntry>,
+}
+
+/// A single line of the log after the header
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogEntry {
+    /// A single move, when players move one after another
+    Move { player: usize, direction: Direction },
+    /// All moves of one turn, when players move simultaneously
+    Turn(Vec<(usize, Direction)>),
+    /// Something that happened apart from the moves of the players, e.g. food spawning or a
+    /// player going out
+    Event(Instruction),
+    /// Time in milliseconds that players have left after their last move, when playing with a
+    /// time control
+    Clock(Vec<(usize, u64)>),
+    /// CPU and wall-clock time in milliseconds that players took for their last move, when
+    /// limiting CPU time
+    Timing(Vec<(usize, u64, u64)>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Log ends in the middle of the header")]
+    IncompleteHeader,
+    #[error("Invalid header on line {0}: \"{1}\"")]
+    InvalidHeader(usize, String),
+    #[error("Invalid line {0}: \"{1}\" ({2})")]
+    InvalidLine(usize, String, InstructionParseError),
+}
+
+impl GameLog {
+    pub fn read(path: &Path) -> Result<Self, LogError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+/// Next line of the header, with its line number
+fn header_line<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(usize, &'a str), LogError> {
+    lines.next().ok_or(LogError::IncompleteHeader)
+}
+
+fn invalid_header(line_nr: usize, line: &str) -> LogError {
+    LogError::InvalidHeader(line_nr, line.to_owned())
+}
+
+fn on_board(setup: &GameSetup, (x, y): (usize, usize)) -> bool {
+    x < setup.width && y < setup.height
+}
+
+impl std::str::FromStr for GameLog {
+    type Err = LogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .peekable();
+
+        let (line_nr, line) = header_line(&mut lines)?;
+        let (width, height) = match parse_usize_pair(line) {
+            Ok((width, height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(invalid_header(line_nr, line)),
+        };
+        let (line_nr, line) = header_line(&mut lines)?;
+        let n_players: usize = line.parse().map_err(|_| invalid_header(line_nr, line))?;
+
+        let mut setup = GameSetup {
+            width,
+            height,
+            starting_positions: Vec::new(),
+            topology: Topology::Torus,
+            walls: Vec::new(),
+            rules: Ruleset::Tron,
+            max_turns: None,
+        };
+        for _ in 0..n_players {
+            let (line_nr, line) = header_line(&mut lines)?;
+            match parse_usize_pair(line) {
+                Ok(pos) if on_board(&setup, pos) => setup.starting_positions.push(pos),
+                _ => return Err(invalid_header(line_nr, line)),
+            }
+        }
+
+        // optional header lines for settings that differ from the classic game
+        let mut simultaneous = false;
+        let mut time_control = None;
+        let mut names = Vec::new();
+        while let Some(&(line_nr, line)) = lines.peek() {
+            match line.split_once(':') {
+                Some(("mode", "simultaneous")) => simultaneous = true,
+                Some(("topology", name)) => {
+                    setup.topology = name.parse().map_err(|_| invalid_header(line_nr, line))?
+                }
+                Some(("rules", name)) => {
+                    setup.rules = name.parse().map_err(|_| invalid_header(line_nr, line))?
+                }
+                Some(("time_control", control)) => {
+                    time_control = Some(control.parse().map_err(|_| invalid_header(line_nr, line))?)
+                }
+                Some(("name", name)) => match name.split_once(':') {
+                    Some((player, name))
+                        if player.parse() == Ok(names.len()) && names.len() < n_players =>
+                    {
+                        names.push(name.to_owned())
+                    }
+                    _ => return Err(invalid_header(line_nr, line)),
+                },
+                Some(("walls", positions)) => {
+                    for pos in positions.split_whitespace() {
+                        match parse_usize_pair(pos) {
+                            Ok(pos) if on_board(&setup, pos) => setup.walls.push(pos),
+                            _ => return Err(invalid_header(line_nr, line)),
+                        }
+                    }
+                }
+                _ => break,
+            }
+            lines.next();
+        }
+
+        let entries = lines
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(line_nr, line)| {
+                parse_entry(line, simultaneous)
+                    .and_then(|entry| check_entry(entry, &setup))
+                    .map_err(|err| LogError::InvalidLine(line_nr, line.to_owned(), err))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            setup,
+            simultaneous,
+            time_control,
+            names,
+            entries,
+        })
+    }
+}
+
+fn parse_entry(line: &str, simultaneous: bool) -> Result<LogEntry, InstructionParseError> {
+    if let Some(times) = line.strip_prefix("clock:") {
+        return times
+            .split_whitespace()
+            .map(|time| {
+                let (player, time) = split_field(time, ':', "remaining time")?;
+                Ok((parse_player(player)?, parse_number(time, "remaining time")?))
+            })
+            .collect::<Result<_, _>>()
+            .map(LogEntry::Clock);
+    }
+    if let Some(timings) = line.strip_prefix("timing:") {
+        return timings
+            .split_whitespace()
+            .map(|timing| {
+                let (player, times) = split_field(timing, ':', "CPU time")?;
+                let (cpu, wall) = split_field(times, ',', "wall-clock time")?;
+                Ok((
+                    parse_player(player)?,
+                    parse_number(cpu, "CPU time")?,
+                    parse_number(wall, "wall-clock time")?,
+                ))
+            })
+            .collect::<Result<_, _>>()
+            .map(LogEntry::Timing);
+    }
+    if simultaneous && !line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return line
+            .split_whitespace()
+            .map(parse_player_move)
+            .collect::<Result<_, _>>()
+            .map(LogEntry::Turn);
+    }
+    match line.parse()? {
+        event @ (Instruction::Food { .. } | Instruction::Out { .. }) => Ok(LogEntry::Event(event)),
+        Instruction::Move { player, direction } => Ok(LogEntry::Move { player, direction }),
+        _ => Err(InstructionParseError::UnknownKeyword(
+            line.split([':', ' ']).next().unwrap_or_default().to_owned(),
+        )),
+    }
+}
+
+/// Splits a field of a clock or timing entry, e.g. `{player}:{time}`
+fn split_field<'a>(
+    field: &'a str,
+    separator: char,
+    missing: &'static str,
+) -> Result<(&'a str, &'a str), InstructionParseError> {
+    field
+        .split_once(separator)
+        .ok_or(InstructionParseError::Missing(missing))
+}
+
+/// The entry, if it only refers to players and cells that exist in the game
+fn check_entry(entry: LogEntry, setup: &GameSetup) -> Result<LogEntry, InstructionParseError> {
+    let n_players = setup.starting_positions.len();
+    let player_exists = |player: &usize| {
+        if *player < n_players {
+            Ok(())
+        } else {
+            Err(InstructionParseError::InvalidPlayer(player.to_string()))
+        }
+    };
+    match &entry {
+        LogEntry::Move { player, .. } | LogEntry::Event(Instruction::Out { player, .. }) => {
+            player_exists(player)?
+        }
+        LogEntry::Turn(moves) => moves
+            .iter()
+            .try_for_each(|(player, _)| player_exists(player))?,
+        LogEntry::Clock(times) => times
+            .iter()
+            .try_for_each(|(player, _)| player_exists(player))?,
+        LogEntry::Timing(timings) => timings
+            .iter()
+            .try_for_each(|(player, ..)| player_exists(player))?,
+        LogEntry::Event(Instruction::Food { position }) => {
+            if !on_board(setup, *position) {
+                let (x, y) = position;
+                return Err(InstructionParseError::InvalidPosition(format!("{x},{y}")));
+            }
+        }
+        LogEntry::Event(_) => {}
+    }
+    Ok(entry)
+}
+
+/// Writes the header in the same format as the games do, so that logs round trip
+fn write_header(f: &mut std::fmt::Formatter<'_>, setup: &GameSetup) -> std::fmt::Result {
+    writeln!(f, "{},{}", setup.width, setup.height)?;
+    writeln!(f, "{}", setup.starting_positions.len())?;
+    for (x, y) in &setup.starting_positions {
+        writeln!(f, "{x},{y}")?;
+    }
+    if setup.topology != Topology::Torus {
+        writeln!(f, "topology:{}", setup.topology)?;
+    }
+    if !setup.walls.is_empty() {
+        let walls = setup
+            .walls
+            .iter()
+            .map(|(x, y)| format!("{x},{y}"))
+            .join(" ");
+        writeln!(f, "walls:{walls}")?;
+    }
+    if setup.rules != Ruleset::Tron {
+        writeln!(f, "rules:{}", setup.rules)?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for GameLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_header(f, &self.setup)?;
+        if let Some(time_control) = self.time_control {
+            writeln!(f, "time_control:{time_control}")?;
+        }
+        for (player, name) in self.names.iter().enumerate() {
+            writeln!(f, "name:{player}:{name}")?;
+        }
+        if self.simultaneous {
+            writeln!(f, "mode:simultaneous")?;
+        }
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Move { player, direction } => write!(f, "{player}:{direction}"),
+            Self::Turn(moves) => write!(
+                f,
+                "{}",
+                moves
+                    .iter()
+                    .map(|(player, direction)| format!("{player}:{direction}"))
+                    .join(" ")
+            ),
+            Self::Event(instruction) => write!(f, "{instruction}"),
+            Self::Clock(times) => write!(
+                f,
+                "clock:{}",
+                times
+                    .iter()
+                    .map(|(player, time)| format!("{player}:{time}"))
+                    .join(" ")
+            ),
+            Self::Timing(timings) => write!(
+                f,
+                "timing:{}",
+                timings
+                    .iter()
+                    .map(|(player, cpu, wall)| format!("{player}:{cpu},{wall}"))
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+/// Writes a log while a game is being played. Every line is flushed as soon as it is written, so
+/// the log is complete up to the last move even if the runner is interrupted.
+pub struct LogWriter {
+    writer: LineWriter<File>,
+}
+
+impl LogWriter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: LineWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Writes the header as it was sent to the players, without player id, followed by the names
+    /// of the players
+    pub fn write_header(
+        &mut self,
+        header: &str,
+        names: &[String],
+        simultaneous: bool,
+    ) -> std::io::Result<()> {
+        writeln!(self.writer, "{header}")?;
+        for (player, name) in names.iter().enumerate() {
+            writeln!(self.writer, "name:{player}:{name}")?;
+        }
+        if simultaneous {
+            writeln!(self.writer, "mode:simultaneous")?;
+        }
+        Ok(())
+    }
+
+    pub fn write_entry(&mut self, entry: &LogEntry) -> std::io::Result<()> {
+        writeln!(self.writer, "{entry}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_instruction::OutReason;
+    use Direction::*;
+
+    #[test]
+    fn roundtrip() {
+        let log = "5,4\n2\n0,1\n3,3\ntopology:box\nwalls:2,0 2,1\nrules:classic:4\nfood:4,0\n0:E\n1:N\nfood:1,1\n";
+        let parsed: GameLog = log.parse().unwrap();
+        assert_eq!(parsed.setup.topology, Topology::Box);
+        assert_eq!(parsed.setup.walls, vec![(2, 0), (2, 1)]);
+        assert_eq!(parsed.setup.rules, Ruleset::Classic { length: 4 });
+        assert_eq!(
+            parsed.entries[1],
+            LogEntry::Move {
+                player: 0,
+                direction: East
+            }
+        );
+        assert_eq!(parsed.to_string(), log);
+    }
+
+    #[test]
+    fn simultaneous() {
+        let log = "3,3\n2\n0,0\n2,2\nmode:simultaneous\n0:E 1:W\n1:N\n";
+        let parsed: GameLog = log.parse().unwrap();
+        assert!(parsed.simultaneous);
+        assert_eq!(
+            parsed.entries,
+            vec![
+                LogEntry::Turn(vec![(0, East), (1, West)]),
+                LogEntry::Turn(vec![(1, North)])
+            ]
+        );
+        assert_eq!(parsed.to_string(), log);
+    }
+
+    #[test]
+    fn clock_and_timing() {
+        let log = "3,3\n2\n0,0\n2,2\ntime_control:1000+10\n0:E\ntiming:0:12,20\nclock:0:980\n1:W\nclock:1:1010\n";
+        let parsed: GameLog = log.parse().unwrap();
+        assert_eq!(
+            parsed.time_control,
+            Some(TimeControl {
+                initial: 1000,
+                increment: 10
+            })
+        );
+        assert_eq!(parsed.entries[1], LogEntry::Timing(vec![(0, 12, 20)]));
+        assert_eq!(parsed.entries[2], LogEntry::Clock(vec![(0, 980)]));
+        assert_eq!(parsed.to_string(), log);
+        assert!(matches!(
+            "3,3\n1\n0,0\nclock:1:980\n".parse::<GameLog>(),
+            Err(LogError::InvalidLine(4, _, _))
+        ));
+    }
+
+    #[test]
+    fn names() {
+        let log = "3,3\n2\n0,0\n2,2\nname:0:deep3\nname:1:a.py#2\nmode:simultaneous\n0:E 1:W\n";
+        let parsed: GameLog = log.parse().unwrap();
+        assert_eq!(parsed.names, ["deep3", "a.py#2"]);
+        assert_eq!(parsed.to_string(), log);
+        assert!(matches!(
+            "3,3\n1\n0,0\nname:1:deep3\n".parse::<GameLog>(),
+            Err(LogError::InvalidHeader(4, _))
+        ));
+    }
+
+    #[test]
+    fn out_reasons() {
+        let log = "3,3\n2\n0,0\n2,2\nmode:simultaneous\n0:E 1:W\nout:1:timeout\nout:0:turn_limit\n";
+        let parsed: GameLog = log.parse().unwrap();
+        assert_eq!(
+            parsed.entries[1],
+            LogEntry::Event(Instruction::Out {
+                player: 1,
+                reason: Some(OutReason::TimeOut)
+            })
+        );
+        assert_eq!(parsed.to_string(), log);
+        assert!("3,3\n2\n0,0\n2,2\nout:2:timeout"
+            .parse::<GameLog>()
+            .is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            "3,3\n2\n0,0".parse::<GameLog>(),
+            Err(LogError::IncompleteHeader)
+        ));
+        assert!(matches!(
+            "3,3\n1\n3,0\n".parse::<GameLog>(),
+            Err(LogError::InvalidHeader(3, _))
+        ));
+        assert!(matches!(
+            "3,3\n1\n0,0\n0:N\n1:S\n".parse::<GameLog>(),
+            Err(LogError::InvalidLine(5, _, InstructionParseError::InvalidPlayer(player)))
+                if player == "1"
+        ));
+        assert!(matches!(
+            "3,3\n1\n0,0\nx\n".parse::<GameLog>(),
+            Err(LogError::InvalidLine(4, _, InstructionParseError::UnknownKeyword(keyword)))
+                if keyword == "x"
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::{self, BufRead, Write};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::classic::ClassicSnakeGame;
+use crate::game::{Direction, TorusSnakeGame};
+use crate::parse_instruction::Instruction;
+use crate::rules::{GameRules, GameSetup, Ruleset};
+use crate::strategy::{read_header, read_line};
+
+/// Humans playing in the same game take turns at the terminal, e.g. when moving simultaneously
+static TERMINAL: Mutex<()> = Mutex::new(());
+
+/// Lets a person play in the terminal. Reads the input of a bot from `input` to keep track of the
+/// game, shows the board whenever the person is asked for a move, and writes the direction they
+/// choose to `output`. The runner enforces `time_limit`, it is only used to tell the person how
+/// long they have and to stop waiting for them.
+pub(crate) fn play(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    time_limit: Option<u64>,
+) -> io::Result<()> {
+    let Some((setup, player)) = read_header(&mut input, &mut output)? else {
+        return Ok(());
+    };
+    match setup.rules {
+        Ruleset::Tron => {
+            play_game::<TorusSnakeGame>(&setup, player, input, &mut output, time_limit)
+        }
+        Ruleset::Classic { .. } => {
+            play_game::<ClassicSnakeGame>(&setup, player, input, &mut output, time_limit)
+        }
+    }
+}
+
+/// Follows the game from the instructions in `input`, which come after the header
+fn play_game<G: GameRules>(
+    setup: &GameSetup,
+    player: usize,
+    mut input: impl BufRead,
+    output: &mut impl Write,
+    time_limit: Option<u64>,
+) -> io::Result<()> {
+    let mut game = G::new(setup);
+    while let Some(line) = read_line(&mut input)? {
+        let Ok(instruction) = line.parse() else {
+            continue;
+        };
+        match instruction {
+            Instruction::AskMove { .. } => {
+                if let Some(direction) = ask_direction(&game, player, time_limit)? {
+                    // the runner does not send us our own moves
+                    game.apply_move(player, direction);
+                    writeln!(output, "{direction}")?;
+                    output.flush()?;
+                }
+            }
+            Instruction::Move { player, direction } => {
+                game.apply_move(player, direction);
+            }
+            Instruction::Out { player, .. } => game.eliminate(player),
+            Instruction::Food { .. } => game.apply_event(&instruction),
+            // we do not accept full-state updates in the handshake
+            Instruction::State(_) => {}
+            Instruction::Stop { reason } => {
+                let _terminal = TERMINAL.lock().unwrap_or_else(PoisonError::into_inner);
+                let reason = reason.map(|reason| format!(" ({reason})"));
+                println!(
+                    "\n{game}\nThe game is over for player {player}{}",
+                    reason.unwrap_or_default()
+                );
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shows the board and waits for the person to press a direction key. `None` if they run out of
+/// time.
+fn ask_direction(
+    game: &impl std::fmt::Display,
+    player: usize,
+    time_limit: Option<u64>,
+) -> io::Result<Option<Direction>> {
+    let _terminal = TERMINAL.lock().unwrap_or_else(PoisonError::into_inner);
+    println!("\n{game}");
+    match time_limit {
+        Some(time_limit) => println!(
+            "Player {player}, your move (arrow keys or N, E, S, W, {:.1}s)",
+            time_limit as f64 / 1000.0
+        ),
+        None => println!("Player {player}, your move (arrow keys or N, E, S, W)"),
+    }
+    let deadline = time_limit.map(|time_limit| Instant::now() + Duration::from_millis(time_limit));
+    let direction = terminal::read_direction(deadline)?;
+    match direction {
+        Some(direction) => println!("You moved {direction}"),
+        None => println!("Out of time"),
+    }
+    Ok(direction)
+}
+
+/// The first direction chosen in the keys that were pressed: an arrow key, or N, E, S or W in any
+/// case. Arrow keys are sent as an escape sequence, e.g. `\x1b[A` for the up arrow.
+fn parse_keys(keys: &[u8]) -> Option<Direction> {
+    let mut keys = keys;
+    while let Some((&key, rest)) = keys.split_first() {
+        match (key, rest) {
+            (b'\x1b', [b'[' | b'O', arrow, rest @ ..]) => {
+                match arrow {
+                    b'A' => return Some(Direction::North),
+                    b'B' => return Some(Direction::South),
+                    b'C' => return Some(Direction::East),
+                    b'D' => return Some(Direction::West),
+                    _ => {}
+                }
+                keys = rest;
+            }
+            (b'n' | b'N', _) => return Some(Direction::North),
+            (b's' | b'S', _) => return Some(Direction::South),
+            (b'e' | b'E', _) => return Some(Direction::East),
+            (b'w' | b'W', _) => return Some(Direction::West),
+            _ => keys = rest,
+        }
+    }
+    None
+}
+
+/// Reads single key presses straight from the terminal, without waiting for enter
+#[cfg(target_os = "linux")]
+mod terminal {
+    use super::parse_keys;
+    use crate::game::Direction;
+    use std::io;
+    use std::time::Instant;
+
+    /// Turns off line buffering and echoing of the terminal until dropped. Does nothing if the
+    /// input is not a terminal.
+    struct RawMode(Option<libc::termios>);
+
+    impl RawMode {
+        fn enable() -> Self {
+            // SAFETY: termios is a plain C struct that tcgetattr fills in
+            let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: only changes the settings of the terminal on stdin
+            unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                    return Self(None);
+                }
+                let original = termios;
+                termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                termios.c_cc[libc::VMIN] = 1;
+                termios.c_cc[libc::VTIME] = 0;
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+                Self(Some(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            if let Some(original) = &self.0 {
+                // SAFETY: restores the settings read in `enable`
+                unsafe {
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+                }
+            }
+        }
+    }
+
+    /// Waits for a direction key until the deadline, if any. Other keys are ignored.
+    pub(super) fn read_direction(deadline: Option<Instant>) -> io::Result<Option<Direction>> {
+        let _raw_mode = RawMode::enable();
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    i32::try_from(left.as_millis()).unwrap_or(i32::MAX).max(1)
+                }
+                None => -1,
+            };
+            let mut poll_fd = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: polls a single valid pollfd
+            match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+                0 => continue,
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+                -1 => return Err(io::Error::last_os_error()),
+                _ => {}
+            }
+            // read directly, so that no keys are left in a buffer for the next move
+            let mut keys = [0; 16];
+            // SAFETY: reads at most the length of the buffer into it
+            let n_read =
+                unsafe { libc::read(libc::STDIN_FILENO, keys.as_mut_ptr().cast(), keys.len()) };
+            match n_read {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                -1 => return Err(io::Error::last_os_error()),
+                n_read => {
+                    if let Some(direction) = parse_keys(&keys[..n_read as usize]) {
+                        return Ok(Some(direction));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reads whole lines, so a key has to be followed by enter. The deadline is not kept, the runner
+/// still times out the player.
+#[cfg(not(target_os = "linux"))]
+mod terminal {
+    use super::parse_keys;
+    use crate::game::Direction;
+    use std::io;
+    use std::time::Instant;
+
+    pub(super) fn read_direction(_deadline: Option<Instant>) -> io::Result<Option<Direction>> {
+        loop {
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if let Some(direction) = parse_keys(line.as_bytes()) {
+                return Ok(Some(direction));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(parse_keys(b"\x1b[A"), Some(North));
+        assert_eq!(parse_keys(b"\x1bOD"), Some(West));
+        assert_eq!(parse_keys(b"x\x1b[Ce"), Some(East));
+        assert_eq!(parse_keys(b"s\n"), Some(South));
+        // the modifiers of a shifted arrow key are skipped, as are other escape sequences
+        assert_eq!(parse_keys(b"\x1b[1;2B"), None);
+        assert_eq!(parse_keys(b"\x1b[Hq"), None);
+        assert_eq!(parse_keys(b""), None);
+    }
+
+    #[test]
+    fn follows_game() {
+        // the game stops before the human is asked for a move, so the terminal is not touched
+        let input = "10,10\n2\n1,1\n5,5\ntopology:box\n1\n0:N\nout:0\nstop\n";
+        let mut output = Vec::new();
+        play(input.as_bytes(), &mut output, None).unwrap();
+        assert!(output.is_empty());
+    }
+}
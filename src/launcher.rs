@@ -0,0 +1,309 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use glob::Pattern;
+
+/// Placeholders that can be used in the command and working directory of a launcher
+const PLACEHOLDERS: [&str; 4] = ["{path}", "{dir}", "{file}", "{stem}"];
+
+/// How to start scripts whose name matches a pattern. The command is split on whitespace into the
+/// program and its arguments, after which the placeholders are filled in, so a script name
+/// containing spaces stays a single argument:
+/// - `{path}`: the script name as given, e.g. `bots/mybot.js`
+/// - `{dir}`: the directory of the script, e.g. `bots`, or `.` if the name has no directory
+/// - `{file}`: the file name of the script, e.g. `mybot.js`
+/// - `{stem}`: the file name without its extension, e.g. `mybot`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Launcher {
+    pattern: Pattern,
+    command: Vec<String>,
+    /// Working directory of the script, the current directory if not given
+    cwd: Option<String>,
+}
+
+/// Launchers tried in order, the first one whose pattern matches the script is used. User
+/// launchers go before the built-in ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Launchers(Vec<Launcher>);
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum LauncherParseError {
+    #[error("Line {0} has no pattern")]
+    MissingPattern(usize),
+    #[error("Line {0} has no command")]
+    MissingCommand(usize),
+    #[error("Invalid pattern \"{0}\" on line {1}")]
+    InvalidPattern(String, usize),
+    #[error("Unknown key \"{0}\" on line {1}, expected pattern, cmd or cwd")]
+    UnknownKey(String, usize),
+    #[error("Unknown placeholder in \"{0}\" on line {1}, expected {{path}}, {{dir}}, {{file}} or {{stem}}")]
+    UnknownPlaceholder(String, usize),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LauncherError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] LauncherParseError),
+}
+
+impl Launcher {
+    /// A launcher for the scripts matching `pattern`. A pattern starting with a dot, e.g. `.js`, is
+    /// short for all files with that extension. Patterns are globs that are matched against the
+    /// file name of the script, or against the whole script name if they contain a `/`.
+    pub fn new(
+        pattern: &str,
+        command: &str,
+        cwd: Option<&str>,
+    ) -> Result<Self, glob::PatternError> {
+        let pattern = match pattern.strip_prefix('.') {
+            Some(extension) if !extension.contains(['/', '*']) => format!("*.{extension}"),
+            _ => pattern.to_string(),
+        };
+        Ok(Self {
+            pattern: Pattern::new(&pattern)?,
+            command: command.split_whitespace().map(str::to_string).collect(),
+            cwd: cwd.map(str::to_string),
+        })
+    }
+
+    fn matches(&self, script: &str) -> bool {
+        if self.pattern.as_str().contains('/') {
+            return self.pattern.matches(script);
+        }
+        Path::new(script)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| self.pattern.matches(name))
+    }
+
+    fn command(&self, script: &str) -> io::Result<Command> {
+        let path = Path::new(script);
+        let dir = match path.parent().and_then(Path::to_str) {
+            None | Some("") => ".",
+            Some(dir) => dir,
+        };
+        let not_a_file = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{script} is not a file name"),
+            )
+        };
+        let file = path.file_name().and_then(|name| name.to_str());
+        let file = file.ok_or_else(not_a_file)?;
+        let stem = path.file_stem().and_then(|stem| stem.to_str());
+        let stem = stem.ok_or_else(not_a_file)?;
+        let fill = |template: &str| {
+            template
+                .replace("{path}", script)
+                .replace("{dir}", dir)
+                .replace("{file}", file)
+                .replace("{stem}", stem)
+        };
+
+        let mut args = self.command.iter().map(|arg| fill(arg));
+        let program = args
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut command = Command::new(program);
+        command.args(args);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(fill(cwd));
+        }
+        Ok(command)
+    }
+}
+
+impl Default for Launchers {
+    /// Python, JavaScript, Ruby, Java and Lua scripts are run with their interpreter from their own
+    /// directory, anything else is assumed to be an executable
+    fn default() -> Self {
+        let python = if cfg!(windows) { "python" } else { "python3" };
+        let builtin = [
+            // as a module, so that it can import other modules next to it
+            (".py", format!("{python} -m {{stem}}"), Some("{dir}")),
+            (".js", "node {file}".to_string(), Some("{dir}")),
+            (".rb", "ruby {file}".to_string(), Some("{dir}")),
+            (".jar", "java -jar {file}".to_string(), Some("{dir}")),
+            (".lua", "lua {file}".to_string(), Some("{dir}")),
+            ("*", "./{path}".to_string(), None),
+        ];
+        Self(
+            builtin
+                .into_iter()
+                .map(|(pattern, command, cwd)| Launcher::new(pattern, &command, cwd).unwrap())
+                .collect(),
+        )
+    }
+}
+
+impl Launchers {
+    /// The built-in launchers preceded by the launchers in a launcher file
+    pub fn load(path: &Path) -> Result<Self, LauncherError> {
+        Ok(std::fs::read_to_string(path)?.parse()?)
+    }
+
+    /// Adds a launcher that takes precedence over all launchers added before
+    pub fn add(&mut self, launcher: Launcher) {
+        self.0.insert(0, launcher);
+    }
+
+    /// Command that starts the script, using the first launcher that matches it
+    pub fn command(&self, script: &str) -> io::Result<Command> {
+        self.0
+            .iter()
+            .find(|launcher| launcher.matches(script))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("no launcher for {script}"),
+                )
+            })?
+            .command(script)
+    }
+}
+
+/// Launcher files have one launcher per line, given as `;`-separated `key=value` pairs, e.g.
+/// `pattern=.kt;cmd=kotlin {file};cwd={dir}`. `cwd` is optional, empty lines and lines starting
+/// with `#` are ignored. Lines higher up in the file take precedence, and all of them over the
+/// built-in launchers.
+impl std::str::FromStr for Launchers {
+    type Err = LauncherParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let user_launchers = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_no, line)| parse_launcher(line, line_no))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut launchers = Self::default();
+        for launcher in user_launchers.into_iter().rev() {
+            launchers.add(launcher);
+        }
+        Ok(launchers)
+    }
+}
+
+fn parse_launcher(line: &str, line_no: usize) -> Result<Launcher, LauncherParseError> {
+    let (mut pattern, mut command, mut cwd) = (None, None, None);
+    for field in line.split(';').filter(|field| !field.trim().is_empty()) {
+        let (key, value) = field.split_once('=').unwrap_or((field, ""));
+        let value = value.trim();
+        match key.trim() {
+            "pattern" => pattern = Some(value),
+            "cmd" => command = Some(value),
+            "cwd" => cwd = Some(value),
+            other => return Err(LauncherParseError::UnknownKey(other.to_string(), line_no)),
+        }
+    }
+    let pattern = pattern
+        .filter(|pattern| !pattern.is_empty())
+        .ok_or(LauncherParseError::MissingPattern(line_no))?;
+    let command = command
+        .filter(|command| !command.is_empty())
+        .ok_or(LauncherParseError::MissingCommand(line_no))?;
+    for template in [Some(command), cwd].into_iter().flatten() {
+        if !has_known_placeholders(template) {
+            return Err(LauncherParseError::UnknownPlaceholder(
+                template.to_string(),
+                line_no,
+            ));
+        }
+    }
+    Launcher::new(pattern, command, cwd)
+        .map_err(|_| LauncherParseError::InvalidPattern(pattern.to_string(), line_no))
+}
+
+/// Whether every `{...}` in the template is one of the placeholders
+fn has_known_placeholders(template: &str) -> bool {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start..];
+        match PLACEHOLDERS.iter().find(|p| rest.starts_with(*p)) {
+            Some(placeholder) => rest = &rest[placeholder.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn cwd(command: &Command) -> Option<String> {
+        command
+            .get_current_dir()
+            .map(|dir| dir.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn builtin_launchers() {
+        let launchers = Launchers::default();
+        let command = launchers.command("bots/my bot.js").unwrap();
+        assert_eq!(args(&command), ["node", "my bot.js"]);
+        assert_eq!(cwd(&command).as_deref(), Some("bots"));
+
+        let command = launchers.command("randommover.py").unwrap();
+        assert_eq!(args(&command)[1..], ["-m", "randommover"]);
+        assert_eq!(cwd(&command).as_deref(), Some("."));
+
+        let command = launchers.command("target/release/bot").unwrap();
+        assert_eq!(args(&command), ["./target/release/bot"]);
+        assert_eq!(cwd(&command), None);
+    }
+
+    #[test]
+    fn launcher_file() {
+        let launchers: Launchers = "# kotlin\n\
+                                    pattern=.kt;cmd=kotlin {file} --fast;cwd={dir}\n\
+                                    \n\
+                                    pattern=bots/*.js;cmd=deno run {path}\n"
+            .parse()
+            .unwrap();
+        let command = launchers.command("bots/a.kt").unwrap();
+        assert_eq!(args(&command), ["kotlin", "a.kt", "--fast"]);
+        assert_eq!(cwd(&command).as_deref(), Some("bots"));
+        // the user launcher only applies to the bots folder
+        assert_eq!(args(&launchers.command("bots/b.js").unwrap())[0], "deno");
+        assert_eq!(args(&launchers.command("other/b.js").unwrap())[0], "node");
+    }
+
+    #[test]
+    fn invalid_launcher_files() {
+        assert_eq!(
+            "cmd=node {file}".parse::<Launchers>(),
+            Err(LauncherParseError::MissingPattern(1))
+        );
+        assert_eq!(
+            "\npattern=.js".parse::<Launchers>(),
+            Err(LauncherParseError::MissingCommand(2))
+        );
+        assert_eq!(
+            "pattern=.js;cmd=node {name}".parse::<Launchers>(),
+            Err(LauncherParseError::UnknownPlaceholder(
+                "node {name}".to_string(),
+                1
+            ))
+        );
+        assert_eq!(
+            "pattern=.js;command=node".parse::<Launchers>(),
+            Err(LauncherParseError::UnknownKey("command".to_string(), 1))
+        );
+        assert_eq!(
+            "pattern=[.js;cmd=node".parse::<Launchers>(),
+            Err(LauncherParseError::InvalidPattern("[.js".to_string(), 1))
+        );
+    }
+}
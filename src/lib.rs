@@ -0,0 +1,41 @@
+//! Runs competitive games of snake between scripts that communicate through stdin and stdout, and
+//! replays them from their logs. The `snakerunner` command line tool is built on top of this
+//! library; see the README for the rules of the game and the I/O interface of the scripts.
+
+pub mod bot;
+pub mod builtin;
+pub mod classic;
+pub mod clock;
+pub mod game;
+pub mod gamelog;
+mod human;
+pub mod launcher;
+pub mod map;
+pub mod parse_instruction;
+mod process;
+pub mod protocol;
+pub mod rules;
+pub mod running;
+pub mod sandbox;
+pub mod showgame;
+pub mod strategy;
+pub mod topology;
+mod transport;
+
+pub use bot::{BotSource, BotSpec};
+pub use classic::ClassicSnakeGame;
+pub use clock::TimeControl;
+pub use game::{Direction, TorusSnakeGame};
+pub use gamelog::{GameLog, LogEntry, LogError, LogWriter};
+pub use launcher::{Launcher, Launchers};
+pub use map::Map;
+pub use parse_instruction::{Instruction, InstructionParseError};
+pub use protocol::{Feature, Handshake};
+pub use rules::{GameRules, GameSetup, MoveOutcome, Ruleset};
+pub use running::{
+    ConfigError, GameResult, GameSettings, LossReason, PlayerResult, Runner, RunnerError,
+};
+pub use sandbox::{ResourceLimit, Sandbox};
+pub use strategy::{Bot, BotFactory};
+pub use topology::Topology;
+pub use transport::Lobby;
@@ -1,13 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
 use clap::{Args, Parser, Subcommand};
+use itertools::Itertools;
 
-mod parse_instruction;
-mod showgame;
-
-mod game;
-mod running;
-use running::{play_game, play_match};
+use snakerunner::launcher::LauncherError;
+use snakerunner::map::MapError;
+use snakerunner::parse_instruction::parse_usize_pair;
+use snakerunner::protocol::PROTOCOL_VERSION;
+use snakerunner::showgame::showgame;
+use snakerunner::{
+    BotSource, BotSpec, Launchers, Lobby, LogError, Map, PlayerResult, Ruleset, Runner,
+    RunnerError, Sandbox, TimeControl, Topology,
+};
 
 #[derive(Parser)]
 #[command(name = "snakerunner", author, version)]
@@ -24,26 +30,24 @@ enum Commands {
     Show(ShowArgs),
     /// Plays a match consisting of multiple games and outputs which script won most games. Plays a tiebreaker if necessary. Starting positions and the order in which the scripts play is randomized for each game.
     Match(MatchArgs),
+    /// Waits for bots to connect over TCP and plays a game (or a match with -n) between them. Every bot sends name:{name} after connecting, and connects again for every game.
+    Serve(ServeArgs),
 }
 
 #[derive(Args)]
 struct RunArgs {
-    /// The names of the scripts you want to run. If the script name ends in .py, it will be run as a python file. Otherwise, it will be assumed to be a compiled executable.
-    #[arg(short, long, num_args(2..), required=true)]
-    scripts: Vec<String>,
+    /// The names of the scripts you want to run. Scripts ending in .py, .js, .rb, .jar or .lua are run with their interpreter, anything else is assumed to be a compiled executable. See --launchers to add other languages. tcp://{host}:{port} and unix://{path} connect to a bot listening on a socket. human lets you play in the terminal, without a time limit unless given as human:{time limit}. builtin:random and builtin:unidirectional are bots built into the runner.
+    #[arg(short, long, num_args(1..), required_unless_present = "bots", value_parser = BotSpec::parse_name)]
+    scripts: Vec<BotSpec>,
+
+    /// A bot given by its command instead of a script, e.g. --bot "name=deep3;cmd=./mybot --depth 3;cwd=bots/". Can be given multiple times, and plays after the scripts. The name is used in the output, the log and the summary. See the README for the format.
+    #[arg(long = "bot", value_name = "SPEC", value_parser = str::parse::<BotSpec>)]
+    bots: Vec<BotSpec>,
 
     /// Specify the starting positions of the players. Randomly assigned if not specified. Format: x and y coordinates separated by a comma with no space in between, and different positions separated by a space. E.g. snakerunner run -s a.py b.py -p 1,2 3,4
     #[arg(short, long, num_args(2..))]
     positions: Option<Vec<String>>,
 
-    /// Width of the playing field
-    #[arg(short = 'x', long, default_value_t = 10)]
-    width: usize,
-
-    /// Height of the playing field
-    #[arg(short = 'y', long, default_value_t = 10)]
-    height: usize,
-
     /// Print all inputs to and outputs from players, as well as the board at every move
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
@@ -52,16 +56,15 @@ struct RunArgs {
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// Time limit for each move in milliseconds. First move gets 10x more time to allow for some setup.
-    #[arg(short, long, default_value_t = 100)]
-    timelimit: u64,
+    #[command(flatten)]
+    game: GameArgs,
 }
 
 #[derive(Args)]
 struct ShowArgs {
     /// Name of the log file to read from
     #[arg(short, long)]
-    input: Option<String>,
+    input: Option<PathBuf>,
 
     /// Timestep between moves in milliseconds
     #[arg(short, long, default_value_t = 500)]
@@ -70,17 +73,13 @@ struct ShowArgs {
 
 #[derive(Args)]
 struct MatchArgs {
-    /// The names of the scripts you want to run. If the script name ends in .py, it will be run as a python file. Otherwise, it will be assumed to be a compiled executable.
-    #[arg(short, long, num_args(2..), required=true)]
-    scripts: Vec<String>,
-
-    /// Width of the playing field
-    #[arg(short = 'x', long, default_value_t = 10)]
-    width: usize,
+    /// The names of the scripts you want to run. Scripts ending in .py, .js, .rb, .jar or .lua are run with their interpreter, anything else is assumed to be a compiled executable. See --launchers to add other languages. tcp://{host}:{port} and unix://{path} connect to a bot listening on a socket. human lets you play in the terminal, without a time limit unless given as human:{time limit}. builtin:random and builtin:unidirectional are bots built into the runner.
+    #[arg(short, long, num_args(1..), required_unless_present = "bots", value_parser = BotSpec::parse_name)]
+    scripts: Vec<BotSpec>,
 
-    /// Height of the playing field
-    #[arg(short = 'y', long, default_value_t = 10)]
-    height: usize,
+    /// A bot given by its command instead of a script, e.g. --bot "name=deep3;cmd=./mybot --depth 3;cwd=bots/". Can be given multiple times, and plays after the scripts. The name is used in the output, the log and the summary. See the README for the format.
+    #[arg(long = "bot", value_name = "SPEC", value_parser = str::parse::<BotSpec>)]
+    bots: Vec<BotSpec>,
 
     /// Number of games to be played. A tiebreaker may be played, so actual amount of games played might be 1 higher
     #[arg(short, long)]
@@ -90,78 +89,369 @@ struct MatchArgs {
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Save logs in this folder
+    #[arg(short, long)]
+    logs: Option<PathBuf>,
+
+    #[command(flatten)]
+    game: GameArgs,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Number of bots to wait for
+    #[arg(long)]
+    players: usize,
+
+    /// Address to listen on. Use 0.0.0.0 to accept bots from other machines.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on
+    #[arg(long, default_value_t = 9000)]
+    port: u16,
+
+    /// Play a match of this many games instead of a single game. A tiebreaker may be played, so actual amount of games played might be 1 higher
+    #[arg(short, long)]
+    n_games: Option<usize>,
+
+    /// Print all inputs to and outputs from players, as well as the board at every move. Only for a single game.
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+
+    /// Name of the file to which the game is logged, or the summary of the match is written [default: log.txt for a game, summary.txt for a match]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Save the logs of the games of a match in this folder
+    #[arg(short, long)]
+    logs: Option<PathBuf>,
+
+    #[command(flatten)]
+    game: GameArgs,
+}
+
+/// Settings of the games, shared by all commands that play games
+#[derive(Args)]
+struct GameArgs {
+    /// Load additional launchers from a file, which tell how to start scripts depending on their name. See the README for the format.
+    #[arg(long)]
+    launchers: Option<PathBuf>,
+
+    /// Width of the playing field
+    #[arg(short = 'x', long, default_value_t = 10)]
+    width: usize,
+
+    /// Height of the playing field
+    #[arg(short = 'y', long, default_value_t = 10)]
+    height: usize,
+
     /// Time limit for each move in milliseconds. First move gets 10x more time to allow for some setup.
     #[arg(short, long, default_value_t = 100)]
     timelimit: u64,
 
-    /// Save logs in this folder
+    /// Play with a chess clock instead of a time limit per move: {initial}+{increment} in milliseconds, e.g. 5000+50. Every player starts with the initial time and gets the increment after every move. The remaining time is sent along with every move request.
+    #[arg(long, conflicts_with = "timelimit")]
+    time_control: Option<TimeControl>,
+
+    /// Apply the time limit to the CPU time used by the scripts instead of wall-clock time, so that a busy machine does not make them time out. Wall-clock time is still limited to 10x the time limit. Only supported on Linux.
+    #[arg(long, default_value_t = false)]
+    cpu_time: bool,
+
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+
+    /// Pause all scripts except the ones that are asked for a move, so that scripts cannot use CPU time while their opponents think. Only supported on Linux.
+    #[arg(long, default_value_t = false)]
+    fair_scheduling: bool,
+
+    /// Time in milliseconds scripts get to exit after the game, before they are terminated (SIGTERM) and, after the same time again, killed
+    #[arg(long, default_value_t = 200)]
+    grace_period: u64,
+
+    /// Ask all players for their move at the same time and resolve each turn as a whole. Players moving into the same cell both lose.
+    #[arg(long, default_value_t = false)]
+    simultaneous: bool,
+
+    /// Surface the game is played on: torus, box (no wrapping), cylinder (wraps horizontally), klein (wraps vertically with mirroring) or projective (wraps with mirroring on both axes)
+    #[arg(long, default_value_t = Topology::Torus)]
+    topology: Topology,
+
+    /// Load walls and starting positions from a map file. The map also determines the width and height of the playing field. See the README for the format.
     #[arg(short, long)]
+    map: Option<PathBuf>,
+
+    /// Ruleset: tron (snakes keep growing) or classic (snakes have a fixed length that grows by eating food). The starting length for classic can be given as classic:{length} [default length: 3]
+    #[arg(long, default_value_t = Ruleset::Tron)]
+    rules: Ruleset,
+
+    /// End the game after this many turns. The longest remaining snake wins, or nobody if several are equally long. [default: 1000 for classic, no limit for tron]
+    #[arg(long)]
+    max_turns: Option<usize>,
+
+    /// Version of the protocol to speak to the bots that are not given one in their spec: 1, or 2 to greet them with the handshake, which they have to answer to play. [default: 1]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=PROTOCOL_VERSION as i64))]
+    protocol: Option<u32>,
+}
+
+impl GameArgs {
+    /// Runner for games between the bots with these settings
+    fn runner(self, mut bots: Vec<BotSpec>) -> Result<Runner, CliError> {
+        for bot in &mut bots {
+            bot.protocol = bot.protocol.or(self.protocol);
+        }
+        let mut runner = Runner::new(bots)
+            .size(self.width, self.height)
+            .time_limit(self.timelimit)
+            .cpu_time(self.cpu_time)
+            .sandbox(self.sandbox.sandbox())
+            .fair_scheduling(self.fair_scheduling)
+            .grace_period(self.grace_period)
+            .simultaneous(self.simultaneous)
+            .topology(self.topology)
+            .rules(self.rules);
+        if let Some(path) = self.map {
+            runner = runner.map(load_map(path)?);
+        }
+        if let Some(path) = self.launchers {
+            runner = runner.launchers(load_launchers(path)?);
+        }
+        if let Some(max_turns) = self.max_turns {
+            runner = runner.max_turns(max_turns);
+        }
+        if let Some(time_control) = self.time_control {
+            runner = runner.time_control(time_control);
+        }
+        Ok(runner)
+    }
+}
+
+/// Resource limits and isolation for the scripts, only supported on Linux
+#[derive(Args)]
+struct SandboxArgs {
+    /// Maximum memory (address space) of each script in megabytes. For python scripts this includes the interpreter.
+    #[arg(long)]
+    memory_limit: Option<u64>,
+
+    /// Maximum CPU time in seconds each script may use during a game
+    #[arg(long)]
+    cpu_limit: Option<u64>,
+
+    /// Maximum number of processes. Note that this counts all processes of your user, not only those of the script.
+    #[arg(long)]
+    max_processes: Option<u64>,
+
+    /// Maximum number of files each script may have open, including stdin, stdout and stderr
+    #[arg(long)]
+    max_open_files: Option<u64>,
+
+    /// Run the scripts without network access (requires user namespaces)
+    #[arg(long, default_value_t = false)]
+    no_network: bool,
+
+    /// Make the directory the scripts run in read-only (requires user namespaces)
+    #[arg(long, default_value_t = false)]
+    read_only: bool,
+}
+
+impl SandboxArgs {
+    fn sandbox(&self) -> Sandbox {
+        Sandbox {
+            memory: self.memory_limit.map(|megabytes| megabytes * 1024 * 1024),
+            cpu_seconds: self.cpu_limit,
+            processes: self.max_processes,
+            open_files: self.max_open_files,
+            isolate_network: self.no_network,
+            read_only_dir: self.read_only,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error("At least two players are needed, {0} were given")]
+    TooFewPlayers(usize),
+    #[error("Could not parse starting position \"{0}\", expected x,y")]
+    InvalidPosition(String),
+    #[error("Could not load map {}: {source}", path.display())]
+    Map { path: PathBuf, source: MapError },
+    #[error("Could not load launchers {}: {source}", path.display())]
+    Launchers {
+        path: PathBuf,
+        source: LauncherError,
+    },
+    #[error("Could not wait for bots on {address}: {source}")]
+    Serve {
+        address: String,
+        source: std::io::Error,
+    },
+    #[error("Could not show game: {0}")]
+    Show(#[from] LogError),
+    #[error(transparent)]
+    Runner(#[from] RunnerError),
+}
+
+impl CliError {
+    /// 2 for invalid arguments, like clap uses for arguments it cannot parse, and 1 for anything
+    /// that went wrong while running
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::TooFewPlayers(_)
+            | Self::InvalidPosition(_)
+            | Self::Map { .. }
+            | Self::Launchers { .. }
+            | Self::Runner(RunnerError::InvalidConfig(_)) => ExitCode::from(2),
+            Self::Show(_) | Self::Serve { .. } | Self::Runner(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
+fn load_map(path: PathBuf) -> Result<Map, CliError> {
+    Map::load(&path).map_err(|source| CliError::Map { path, source })
+}
+
+/// The scripts followed by the bots given by their command
+fn players(scripts: Vec<BotSpec>, bots: Vec<BotSpec>) -> Result<Vec<BotSpec>, CliError> {
+    let players = scripts.into_iter().chain(bots).collect_vec();
+    if players.len() < 2 {
+        return Err(CliError::TooFewPlayers(players.len()));
+    }
+    Ok(players)
+}
+
+fn load_launchers(path: PathBuf) -> Result<Launchers, CliError> {
+    Launchers::load(&path).map_err(|source| CliError::Launchers { path, source })
+}
+
+fn run(runargs: RunArgs) -> Result<(), CliError> {
+    // parse starting positions
+    let starting_config = match runargs.positions {
+        None => None,
+        Some(vector) => Some(
+            vector
+                .into_iter()
+                .map(|coords_str| {
+                    parse_usize_pair(&coords_str).map_err(|_| CliError::InvalidPosition(coords_str))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+
+    let mut runner = runargs
+        .game
+        .runner(players(runargs.scripts, runargs.bots)?)?
+        .log_file(runargs.output.unwrap_or(PathBuf::from("log.txt")))
+        .verbose(runargs.verbose);
+    if let Some(starting_positions) = starting_config {
+        runner = runner.starting_positions(starting_positions);
+    }
+    play_game(&runner)
+}
+
+/// Plays a single game and prints its outcome
+fn play_game(runner: &Runner) -> Result<(), CliError> {
+    let result = runner.play_game()?;
+    let names = runner.bots().iter().map(|bot| &bot.name).collect_vec();
+    let settings = runner.settings();
+    if let Some(winner) = result.winner {
+        println!("Player {winner} ({}) won!", names[winner]);
+    } else if settings.simultaneous || settings.max_turns.is_some() {
+        println!("The game ended in a draw, no winner");
+    } else {
+        println!("Error caused all remaining players to quit before winner could be determined");
+    };
+    // tactical losses are part of the game, anything else is most likely a bug in the script
+    for (player, player_result) in result.player_results.iter().enumerate() {
+        if let PlayerResult::Loser(reason) = player_result {
+            if reason.is_error() {
+                println!("Player {player} ({}) {reason}", names[player]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn play_match(matchargs: MatchArgs) -> Result<(), CliError> {
+    let runner = matchargs
+        .game
+        .runner(players(matchargs.scripts, matchargs.bots)?)?;
+    let summary_filename = matchargs.output.unwrap_or(PathBuf::from("summary.txt"));
+    report_match(
+        &runner,
+        matchargs.n_games,
+        &summary_filename,
+        matchargs.logs,
+    )
+}
+
+/// Plays a match and prints the winner
+fn report_match(
+    runner: &Runner,
+    n_games: usize,
+    summary_filename: &Path,
     logs: Option<PathBuf>,
+) -> Result<(), CliError> {
+    let winner = runner.play_match(n_games, summary_filename, logs.as_deref())?;
+    println!(
+        "Player {winner} ({}) won the match!",
+        runner.bots()[winner].name
+    );
+    Ok(())
 }
 
-fn main() {
-    let cli = Cli::parse();
-    match cli.command {
-        Commands::Run(runargs) => {
-            // parse starting positions
-            let starting_config = match runargs.positions {
-                None => None,
-                Some(vector) => {
-                    if vector.len() != runargs.scripts.len() {
-                        println!("Incorrect number of starting positions");
-                        return;
-                    }
-                    let mut starting_coords = Vec::new();
-                    for coords_str in vector {
-                        match showgame::parse_usize_pair(&coords_str) {
-                            Ok(coords) => {
-                                starting_coords.push(coords);
-                            }
-                            Err(_) => {
-                                println!("Could not parse coordinates");
-                                return;
-                            }
-                        }
-                    }
-                    Some(starting_coords)
-                }
-            };
-
-            // play the game!
-            if let Some((winner, _)) = play_game(
-                &runargs.scripts.iter().map(String::as_str).collect(),
-                starting_config,
-                runargs.width,
-                runargs.height,
-                Some(&runargs.output.unwrap_or(PathBuf::from("log.txt"))),
-                runargs.verbose,
-                runargs.timelimit,
-            ) {
-                println!("Player {winner} won!");
-            } else {
-                println!(
-                    "Error caused all remaining players to quit before winner could be determined"
-                );
-            };
+/// Waits for bots to connect, then plays a game or match between them
+fn serve(serveargs: ServeArgs) -> Result<(), CliError> {
+    if serveargs.players < 2 {
+        return Err(CliError::TooFewPlayers(serveargs.players));
+    }
+    let address = format!("{}:{}", serveargs.host, serveargs.port);
+    let lobby = Lobby::bind(&address).map_err(|source| CliError::Serve {
+        address: address.clone(),
+        source,
+    })?;
+    println!("Waiting for {} bots on {address}", serveargs.players);
+    let names = lobby.gather(serveargs.players);
+    println!("Connected: {}", names.join(", "));
+
+    let lobby = Arc::new(lobby);
+    let bots = names
+        .into_iter()
+        .map(|name| BotSpec {
+            name,
+            source: BotSource::Lobby(Arc::clone(&lobby)),
+            protocol: None,
+        })
+        .collect();
+    let runner = serveargs.game.runner(bots)?;
+    match serveargs.n_games {
+        Some(n_games) => {
+            let summary_filename = serveargs.output.unwrap_or(PathBuf::from("summary.txt"));
+            report_match(&runner, n_games, &summary_filename, serveargs.logs)
         }
+        None => play_game(
+            &runner
+                .log_file(serveargs.output.unwrap_or(PathBuf::from("log.txt")))
+                .verbose(serveargs.verbose),
+        ),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Commands::Run(runargs) => run(runargs),
         Commands::Show(showargs) => {
-            showgame::showgame(
-                &showargs.input.unwrap_or("log.txt".into()),
-                showargs.timestep,
-            )
-            .unwrap();
+            let logfile = showargs.input.unwrap_or(PathBuf::from("log.txt"));
+            showgame(&logfile, showargs.timestep).map_err(CliError::from)
         }
-        Commands::Match(matchargs) => {
-            let winner = play_match(
-                matchargs.scripts.iter().map(String::as_str).collect(),
-                matchargs.width,
-                matchargs.height,
-                matchargs.n_games,
-                matchargs.timelimit,
-                &matchargs.output.unwrap_or(PathBuf::from("summary.txt")),
-                matchargs.logs,
-            );
-            println!("Player {winner} won the match!");
+        Commands::Match(matchargs) => play_match(matchargs),
+        Commands::Serve(serveargs) => serve(serveargs),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            err.exit_code()
         }
     }
 }
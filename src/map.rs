@@ -0,0 +1,126 @@
+use std::path::Path;
+
+/// A board layout loaded from a map file. Map files are ASCII grids with one line per row: `#` is a
+/// wall, `.` is an empty cell and a digit `n` is the starting cell of player `n`.
+///
+/// ```text
+/// ..........
+/// .0..##..1.
+/// ....##....
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Map {
+    pub width: usize,
+    pub height: usize,
+    pub walls: Vec<(usize, usize)>,
+    /// Starting positions, ordered by player. Empty if the map does not specify any
+    pub starting_positions: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum MapParseError {
+    #[error("Map is empty")]
+    Empty,
+    #[error("Row {0} has a different length than the first row")]
+    RaggedRow(usize),
+    #[error("Invalid character '{0}' in row {1}")]
+    InvalidCharacter(char, usize),
+    #[error("Starting cell of player {0} appears more than once")]
+    DuplicateStart(usize),
+    #[error("Starting cell of player {0} is missing")]
+    MissingStart(usize),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MapError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] MapParseError),
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Self, MapError> {
+        Ok(std::fs::read_to_string(path)?.parse()?)
+    }
+}
+
+impl std::str::FromStr for Map {
+    type Err = MapParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<&str> = s
+            .lines()
+            .map(str::trim_end)
+            .filter(|row| !row.is_empty())
+            .collect();
+        let Some(first_row) = rows.first() else {
+            return Err(MapParseError::Empty);
+        };
+        let width = first_row.chars().count();
+
+        let mut walls = Vec::new();
+        let mut starts = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(MapParseError::RaggedRow(y));
+            }
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => walls.push((x, y)),
+                    '.' => {}
+                    digit if digit.is_ascii_digit() => {
+                        let player = digit as usize - '0' as usize;
+                        if starts.iter().any(|(p, _)| *p == player) {
+                            return Err(MapParseError::DuplicateStart(player));
+                        }
+                        starts.push((player, (x, y)));
+                    }
+                    other => return Err(MapParseError::InvalidCharacter(other, y)),
+                }
+            }
+        }
+
+        starts.sort();
+        let mut starting_positions = Vec::new();
+        for (expected_player, (player, pos)) in starts.into_iter().enumerate() {
+            if player != expected_player {
+                return Err(MapParseError::MissingStart(expected_player));
+            }
+            starting_positions.push(pos);
+        }
+
+        Ok(Self {
+            width,
+            height: rows.len(),
+            walls,
+            starting_positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_map() {
+        let map: Map = "...#\n1..#\n..0.\n".parse().unwrap();
+        assert_eq!(map.width, 4);
+        assert_eq!(map.height, 3);
+        assert_eq!(map.walls, vec![(3, 0), (3, 1)]);
+        assert_eq!(map.starting_positions, vec![(2, 2), (0, 1)]);
+    }
+
+    #[test]
+    fn invalid_maps() {
+        assert_eq!("".parse::<Map>(), Err(MapParseError::Empty));
+        assert_eq!("...\n..".parse::<Map>(), Err(MapParseError::RaggedRow(1)));
+        assert_eq!(
+            "..x".parse::<Map>(),
+            Err(MapParseError::InvalidCharacter('x', 0))
+        );
+        assert_eq!("0.0".parse::<Map>(), Err(MapParseError::DuplicateStart(0)));
+        assert_eq!("0.2".parse::<Map>(), Err(MapParseError::MissingStart(1)));
+    }
+}
@@ -1,60 +1,367 @@
-use anyhow::Result;
 use std::str::FromStr;
 
-// use crate::showgame::parse_player_move;
 use crate::game::Direction;
+use crate::rules::GameState;
 
-#[derive(Debug, PartialEq)]
+/// A message from the runner to a bot. Written as a line like `0:N` (see `Display` and `FromStr`),
+/// or as a JSON object like `{"type":"move","player":0,"direction":"N"}` (see `to_json` and
+/// `from_json`) to bots that speak JSON.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Instruction {
-    AskMove,
-    Move { player: usize, direction: Direction },
-    Out { player: usize },
-    Stop,
+    /// Ask for a move. With a time control, the time the player has left in milliseconds is sent
+    /// along with it
+    AskMove {
+        #[serde(rename = "time_left", default, skip_serializing_if = "Option::is_none")]
+        remaining_time: Option<u64>,
+    },
+    Move {
+        player: usize,
+        direction: Direction,
+    },
+    /// Another player is out of the game. Bots that speak version 2 of the protocol are told why
+    Out {
+        player: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<OutReason>,
+    },
+    Food {
+        position: (usize, usize),
+    },
+    /// The whole board, sent before every move request to bots that accept full-state updates
+    State(GameState),
+    /// The bot has to stop. A bot that lost and speaks version 2 of the protocol is told why
+    Stop {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<OutReason>,
+    },
 }
 
+/// Why a player is out of the game, as sent to the bots and written to the log. See `LossReason`
+/// for the details the runner keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutReason {
+    LosingMove,
+    #[serde(rename = "timeout")]
+    TimeOut,
+    InvalidInput,
+    TurnLimit,
+    Crashed,
+    ClosedStdout,
+    BrokenPipe,
+    SpawnFailed,
+    LimitExceeded,
+}
+
+impl OutReason {
+    const ALL: [Self; 9] = [
+        Self::LosingMove,
+        Self::TimeOut,
+        Self::InvalidInput,
+        Self::TurnLimit,
+        Self::Crashed,
+        Self::ClosedStdout,
+        Self::BrokenPipe,
+        Self::SpawnFailed,
+        Self::LimitExceeded,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::LosingMove => "losing_move",
+            Self::TimeOut => "timeout",
+            Self::InvalidInput => "invalid_input",
+            Self::TurnLimit => "turn_limit",
+            Self::Crashed => "crashed",
+            Self::ClosedStdout => "closed_stdout",
+            Self::BrokenPipe => "broken_pipe",
+            Self::SpawnFailed => "spawn_failed",
+            Self::LimitExceeded => "limit_exceeded",
+        }
+    }
+}
+
+impl std::fmt::Display for OutReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Unknown reason")]
+pub struct UnknownReason;
+
+impl FromStr for OutReason {
+    type Err = UnknownReason;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|reason| reason.name() == s)
+            .ok_or(UnknownReason)
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AskMove {
+                remaining_time: None,
+            } => write!(f, "move"),
+            Self::AskMove {
+                remaining_time: Some(time),
+            } => write!(f, "move {time}"),
+            Self::Move { player, direction } => write!(f, "{player}:{direction}"),
+            Self::Out {
+                player,
+                reason: None,
+            } => write!(f, "out:{player}"),
+            Self::Out {
+                player,
+                reason: Some(reason),
+            } => write!(f, "out:{player}:{reason}"),
+            Self::Food { position: (x, y) } => write!(f, "food:{x},{y}"),
+            Self::State(state) => write!(f, "state:{state}"),
+            Self::Stop { reason: None } => write!(f, "stop"),
+            Self::Stop {
+                reason: Some(reason),
+            } => write!(f, "stop:{reason}"),
+        }
+    }
+}
+
+/// Why a line could not be parsed, with the part of the line that is wrong
 #[derive(Debug, thiserror::Error, PartialEq)]
-#[error("Could not be parsed to instruction")]
-pub struct InstructionParseError {}
+pub enum InstructionParseError {
+    #[error("Empty line")]
+    Empty,
+    #[error("Unknown keyword \"{0}\"")]
+    UnknownKeyword(String),
+    #[error("Missing {0}")]
+    Missing(&'static str),
+    #[error("Invalid player index \"{0}\"")]
+    InvalidPlayer(String),
+    #[error("Invalid direction \"{0}\", expected N, E, S or W")]
+    InvalidDirection(String),
+    #[error("Invalid {0} \"{1}\", expected a number")]
+    InvalidNumber(&'static str, String),
+    #[error("Invalid position \"{0}\", expected {{x}},{{y}}")]
+    InvalidPosition(String),
+    #[error("Unknown reason \"{0}\"")]
+    UnknownReason(String),
+    #[error("Invalid cell \"{0}\", expected ., #, * or a player index")]
+    InvalidCell(String),
+    #[error("Unexpected \"{0}\" after the instruction")]
+    ExtraTokens(String),
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(String),
+}
+
+impl Instruction {
+    /// The instruction as a line of the JSON protocol
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("instructions only contain numbers and strings")
+    }
+
+    /// Parses a line of the JSON protocol
+    pub fn from_json(line: &str) -> Result<Self, InstructionParseError> {
+        serde_json::from_str(line)
+            .map_err(|err| InstructionParseError::InvalidJson(err.to_string()))
+    }
+}
+
+/// Splits the rest of a line into fields, e.g. the `{player}:{reason}` of `out:{player}:{reason}`
+struct Fields<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(rest: &'a str) -> Self {
+        Self { rest: Some(rest) }
+    }
+
+    /// The next field, up to `separator` or the end of the line
+    fn next(
+        &mut self,
+        separator: char,
+        name: &'static str,
+    ) -> Result<&'a str, InstructionParseError> {
+        let rest = self.rest.ok_or(InstructionParseError::Missing(name))?;
+        let (field, rest) = match rest.split_once(separator) {
+            Some((field, rest)) => (field, Some(rest)),
+            None => (rest, None),
+        };
+        self.rest = rest;
+        Ok(field)
+    }
+
+    /// Like `next`, for a field that may be left out at the end of the line
+    fn optional(&mut self, separator: char) -> Option<&'a str> {
+        self.next(separator, "").ok()
+    }
+
+    /// Fails if the line goes on
+    fn end(self) -> Result<(), InstructionParseError> {
+        match self.rest {
+            None => Ok(()),
+            Some(rest) => Err(InstructionParseError::ExtraTokens(rest.to_owned())),
+        }
+    }
+}
+
+/// The field, which has to be a single token
+fn token(field: &str) -> Result<&str, InstructionParseError> {
+    match field.split_once(char::is_whitespace) {
+        Some((token, rest)) => match rest.trim() {
+            "" => Ok(token),
+            rest => Err(InstructionParseError::ExtraTokens(rest.to_owned())),
+        },
+        None => Ok(field),
+    }
+}
+
+/// Digits only, so that signs and spaces are not accepted in the middle of a line
+fn parse_digits<T: FromStr>(field: &str) -> Option<T> {
+    if field.is_empty() || !field.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    field.parse().ok()
+}
+
+pub(crate) fn parse_number<T: FromStr>(
+    field: &str,
+    name: &'static str,
+) -> Result<T, InstructionParseError> {
+    let field = token(field)?;
+    parse_digits(field).ok_or_else(|| InstructionParseError::InvalidNumber(name, field.to_owned()))
+}
+
+pub(crate) fn parse_player(field: &str) -> Result<usize, InstructionParseError> {
+    let field = token(field)?;
+    parse_digits(field).ok_or_else(|| InstructionParseError::InvalidPlayer(field.to_owned()))
+}
+
+fn parse_direction(field: &str) -> Result<Direction, InstructionParseError> {
+    let field = token(field)?;
+    field
+        .parse()
+        .map_err(|_| InstructionParseError::InvalidDirection(field.to_owned()))
+}
+
+fn parse_reason(field: &str) -> Result<OutReason, InstructionParseError> {
+    let field = token(field)?;
+    field
+        .parse()
+        .map_err(|_| InstructionParseError::UnknownReason(field.to_owned()))
+}
 
 impl FromStr for Instruction {
     type Err = InstructionParseError;
 
+    /// Parses a line of the line protocol. Leading and trailing whitespace is ignored.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use Instruction::*;
-        match s {
-            "stop" => Ok(Stop),
-            "move" => Ok(AskMove),
-            instr if &instr[..3] == "out" => {
-                let player = *&instr[4..]
-                    .parse::<usize>()
-                    .map_err(|_| InstructionParseError {})?;
-                Ok(Out { player })
+        use InstructionParseError as E;
+        let line = s.trim();
+        if line.is_empty() {
+            return Err(E::Empty);
+        }
+        // the keyword (or player) ends at the first separator
+        let (keyword, separator, rest) = match line.find([':', ' ']) {
+            Some(idx) => (&line[..idx], Some(&line[idx..=idx]), &line[idx + 1..]),
+            None => (line, None, ""),
+        };
+        match (keyword, separator) {
+            ("move", None) => Ok(AskMove {
+                remaining_time: None,
+            }),
+            ("move", Some(" ")) => Ok(AskMove {
+                remaining_time: Some(parse_number(rest, "remaining time")?),
+            }),
+            ("stop", None) => Ok(Stop { reason: None }),
+            ("stop", Some(":")) => Ok(Stop {
+                reason: Some(parse_reason(rest)?),
+            }),
+            ("out", Some(":")) => {
+                let mut fields = Fields::new(rest);
+                let player = parse_player(fields.next(':', "player")?)?;
+                let reason = fields.optional(':').map(parse_reason).transpose()?;
+                fields.end()?;
+                Ok(Out { player, reason })
             }
-            instr => {
-                let (player, direction) =
-                    parse_player_move(instr).map_err(|_| InstructionParseError {})?;
+            ("food", Some(":")) => Ok(Food {
+                position: parse_usize_pair(token(rest)?)?,
+            }),
+            ("state", Some(":")) => Ok(State(parse_state(rest)?)),
+            ("out", None) => Err(E::Missing("player")),
+            ("food", None) => Err(E::Missing("position")),
+            ("state", None) => Err(E::Missing("turn")),
+            ("move" | "stop" | "out" | "food" | "state", Some(_)) => {
+                Err(E::ExtraTokens(line[keyword.len()..].to_owned()))
+            }
+            // anything else that starts like a number is meant to be a move
+            (player, _)
+                if player.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') =>
+            {
+                let (player, direction) = parse_player_move(line)?;
                 Ok(Move { player, direction })
             }
+            (keyword, _) => Err(E::UnknownKeyword(keyword.to_owned())),
         }
     }
 }
 
-#[derive(Debug, PartialEq, thiserror::Error)]
-pub enum ParseError {
-    // TODO what to do with the different parsing errors?
-    #[error("Wrong amount of arguments")]
-    ArgNoErr,
-    // #[error("Invalid move")]
-    // MoveErr,
-}
-
-pub fn parse_player_move(input: &str) -> Result<(usize, Direction)> {
-    let mut args = input.split(":");
-    let player: usize = args.next().ok_or(ParseError::ArgNoErr)?.parse()?;
-    let direction: Direction = args.next().ok_or(ParseError::ArgNoErr)?.parse()?;
+/// Parses a move written as `{player}:{direction}`, e.g. `0:N`
+pub fn parse_player_move(input: &str) -> Result<(usize, Direction), InstructionParseError> {
+    let mut fields = Fields::new(input.trim());
+    let player = parse_player(fields.next(':', "player")?)?;
+    let direction = parse_direction(fields.next(':', "direction")?)?;
+    fields.end()?;
     Ok((player, direction))
 }
 
+/// Parses a state written as `{turn}:{alive}:{heads}:{board}`, see `GameState`
+fn parse_state(input: &str) -> Result<GameState, InstructionParseError> {
+    let mut fields = Fields::new(input);
+    let turn = parse_number(fields.next(':', "turn")?, "turn")?;
+    let alive = fields.next(':', "alive players")?;
+    let heads = fields.next(':', "heads")?;
+    let board = fields.next(':', "board")?;
+    fields.end()?;
+    Ok(GameState {
+        turn,
+        alive: alive
+            .split(',')
+            .filter(|player| !player.is_empty())
+            .map(parse_player)
+            .collect::<Result<_, _>>()?,
+        heads: heads
+            .split_whitespace()
+            .map(parse_usize_pair)
+            .collect::<Result<_, _>>()?,
+        board: board
+            .split_whitespace()
+            .map(|row| {
+                row.split(',')
+                    .map(|cell| {
+                        cell.parse()
+                            .map_err(|_| InstructionParseError::InvalidCell(cell.to_owned()))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Parses a position or size written as `{x},{y}`
+pub fn parse_usize_pair(input: &str) -> Result<(usize, usize), InstructionParseError> {
+    input
+        .split_once(',')
+        .and_then(|(x, y)| Some((parse_digits(x)?, parse_digits(y)?)))
+        .ok_or_else(|| InstructionParseError::InvalidPosition(input.to_owned()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,27 +369,172 @@ mod tests {
     use Instruction::*;
     #[test]
     fn stop() {
-        assert_eq!("stop".parse(), Ok(Stop));
+        assert_eq!("stop".parse(), Ok(Stop { reason: None }));
+        assert_eq!(
+            "stop:invalid_input".parse(),
+            Ok(Stop {
+                reason: Some(OutReason::InvalidInput)
+            })
+        );
+        assert_eq!(
+            "stop:bored".parse::<Instruction>(),
+            Err(InstructionParseError::UnknownReason("bored".to_string()))
+        );
     }
 
     #[test]
     fn ask_move() {
-        assert_eq!("move".parse(), Ok(AskMove));
+        assert_eq!(
+            "move".parse(),
+            Ok(AskMove {
+                remaining_time: None
+            })
+        );
+        assert_eq!(
+            "move 4950".parse(),
+            Ok(AskMove {
+                remaining_time: Some(4950)
+            })
+        );
+        assert_eq!(
+            "move soon".parse::<Instruction>(),
+            Err(InstructionParseError::InvalidNumber(
+                "remaining time",
+                "soon".to_string()
+            ))
+        );
     }
 
     #[test]
     fn out() {
-        assert_eq!("out:12".parse(), Ok(Out { player: 12 }));
+        assert_eq!(
+            "out:12".parse(),
+            Ok(Out {
+                player: 12,
+                reason: None
+            })
+        );
+        assert_eq!(
+            "out:2:timeout".parse(),
+            Ok(Out {
+                player: 2,
+                reason: Some(OutReason::TimeOut)
+            })
+        );
+        for reason in OutReason::ALL {
+            let out = Out {
+                player: 0,
+                reason: Some(reason),
+            };
+            assert_eq!(out.to_string().parse(), Ok(out.clone()));
+            assert_eq!(Instruction::from_json(&out.to_json()), Ok(out));
+        }
         assert_eq!(
             "out:-1".parse::<Instruction>(),
-            Err(InstructionParseError {})
+            Err(InstructionParseError::InvalidPlayer("-1".to_string()))
         );
         assert_eq!(
             "out:bloop".parse::<Instruction>(),
-            Err(InstructionParseError {})
+            Err(InstructionParseError::InvalidPlayer("bloop".to_string()))
+        );
+    }
+
+    #[test]
+    fn food() {
+        assert_eq!("food:3,4".parse(), Ok(Food { position: (3, 4) }));
+        assert_eq!(
+            "food:3".parse::<Instruction>(),
+            Err(InstructionParseError::InvalidPosition("3".to_string()))
+        );
+    }
+
+    #[test]
+    fn state() {
+        use crate::rules::Cell;
+        let state = GameState {
+            turn: 4,
+            alive: vec![1],
+            heads: vec![(0, 0), (1, 1)],
+            board: vec![
+                vec![Cell::Snake(0), Cell::Wall],
+                vec![Cell::Food, Cell::Snake(1)],
+            ],
+        };
+        let line = "state:4:1:0,0 1,1:0,# *,1";
+        assert_eq!(State(state.clone()).to_string(), line);
+        assert_eq!(line.parse(), Ok(State(state.clone())));
+        assert_eq!(
+            State(state).to_json(),
+            r##"{"type":"state","turn":4,"alive":[1],"heads":[[0,0],[1,1]],"board":[["0","#"],["*","1"]]}"##
+        );
+        assert_eq!(
+            "state:4:1:0,0 1,1:0,x *,1".parse::<Instruction>(),
+            Err(InstructionParseError::InvalidCell("x".to_string()))
         );
     }
 
+    #[test]
+    fn json() {
+        let instructions = [
+            (
+                AskMove {
+                    remaining_time: None,
+                },
+                r#"{"type":"ask_move"}"#,
+            ),
+            (
+                AskMove {
+                    remaining_time: Some(4950),
+                },
+                r#"{"type":"ask_move","time_left":4950}"#,
+            ),
+            (
+                Move {
+                    player: 1,
+                    direction: West,
+                },
+                r#"{"type":"move","player":1,"direction":"W"}"#,
+            ),
+            (
+                Out {
+                    player: 2,
+                    reason: None,
+                },
+                r#"{"type":"out","player":2}"#,
+            ),
+            (
+                Out {
+                    player: 2,
+                    reason: Some(OutReason::TimeOut),
+                },
+                r#"{"type":"out","player":2,"reason":"timeout"}"#,
+            ),
+            (
+                Food { position: (3, 4) },
+                r#"{"type":"food","position":[3,4]}"#,
+            ),
+            (Stop { reason: None }, r#"{"type":"stop"}"#),
+            (
+                Stop {
+                    reason: Some(OutReason::InvalidInput),
+                },
+                r#"{"type":"stop","reason":"invalid_input"}"#,
+            ),
+        ];
+        for (instruction, json) in instructions {
+            assert_eq!(instruction.to_json(), json);
+            assert_eq!(Instruction::from_json(json), Ok(instruction));
+        }
+        assert!(matches!(
+            Instruction::from_json(r#"{"type":"move","player":1,"direction":"up"}"#),
+            Err(InstructionParseError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Instruction::from_json("0:N"),
+            Err(InstructionParseError::InvalidJson(_))
+        ));
+    }
+
     #[test]
     fn move_test() {
         assert_eq!(
@@ -101,7 +553,51 @@ mod tests {
         );
         assert_eq!(
             "14:zoom".parse::<Instruction>(),
-            Err(InstructionParseError {})
+            Err(InstructionParseError::InvalidDirection("zoom".to_string()))
         );
+        assert_eq!(
+            "15:south".parse(),
+            Ok(Move {
+                player: 15,
+                direction: South
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        use InstructionParseError as E;
+        let cases = [
+            ("", E::Empty),
+            ("  \n", E::Empty),
+            ("m", E::UnknownKeyword("m".to_string())),
+            ("hello:2", E::UnknownKeyword("hello".to_string())),
+            ("é:N", E::UnknownKeyword("é".to_string())),
+            ("0", E::Missing("direction")),
+            ("0:", E::InvalidDirection("".to_string())),
+            (":N", E::UnknownKeyword("".to_string())),
+            ("+1:N", E::InvalidPlayer("+1".to_string())),
+            (
+                "99999999999999999999999:N",
+                E::InvalidPlayer("99999999999999999999999".to_string()),
+            ),
+            ("0:N x", E::ExtraTokens("x".to_string())),
+            ("0:N:S", E::ExtraTokens("S".to_string())),
+            ("out", E::Missing("player")),
+            ("out:", E::InvalidPlayer("".to_string())),
+            ("out:1:timeout:x", E::ExtraTokens("x".to_string())),
+            ("out 1", E::ExtraTokens(" 1".to_string())),
+            ("move:500", E::ExtraTokens(":500".to_string())),
+            ("move 500 600", E::ExtraTokens("600".to_string())),
+            ("stop now", E::ExtraTokens(" now".to_string())),
+            ("food:3,-4", E::InvalidPosition("3,-4".to_string())),
+            ("food:3,x,4", E::InvalidPosition("3,x,4".to_string())),
+            ("food:3,4 5,5", E::ExtraTokens("5,5".to_string())),
+            ("state:4:1", E::Missing("heads")),
+            ("state:4:x:0,0:0", E::InvalidPlayer("x".to_string())),
+        ];
+        for (line, err) in cases {
+            assert_eq!(line.parse::<Instruction>(), Err(err), "{line:?}");
+        }
     }
 }
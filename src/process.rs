@@ -0,0 +1,295 @@
+use std::net::Shutdown;
+use std::process::{Child, ExitStatus};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::clock::cpu_time;
+use crate::transport::{Peer, Socket};
+
+/// The processes of the players in a game. Every script runs in its own process group, so that
+/// signals also reach the processes it starts itself. With fair scheduling, a process is only
+/// running while its player is asked for a move, so that it cannot use CPU time while its
+/// opponents think. Bots connected through a socket are not managed by the runner, so the only
+/// thing we can do with them is close the connection. Bots running in a thread of the runner
+/// stop by themselves once their input is closed, they are only waited for.
+pub(crate) struct Processes {
+    children: Vec<Option<Child>>, // None if the script could not be started or is no process
+    sockets: Vec<Option<Socket>>,
+    threads: Vec<Option<JoinHandle<()>>>,
+    fair_scheduling: bool,
+}
+
+impl Processes {
+    /// Takes over the started processes and connected sockets, `None` for bots that could not
+    /// be started. With fair scheduling, processes are paused until their player is asked for
+    /// its first move.
+    pub(crate) fn new(peers: Vec<Option<Peer>>, fair_scheduling: bool) -> Self {
+        let mut processes = Self {
+            children: Vec::with_capacity(peers.len()),
+            sockets: Vec::with_capacity(peers.len()),
+            threads: Vec::with_capacity(peers.len()),
+            fair_scheduling,
+        };
+        for peer in peers {
+            let (child, socket, thread) = match peer {
+                Some(Peer::Process(child)) => (Some(child), None, None),
+                Some(Peer::Socket(socket)) => (None, Some(socket), None),
+                Some(Peer::Thread(thread)) => (None, None, Some(thread)),
+                None => (None, None, None),
+            };
+            processes.children.push(child);
+            processes.sockets.push(socket);
+            processes.threads.push(thread);
+        }
+        for player in 0..processes.children.len() {
+            processes.pause(player);
+        }
+        processes
+    }
+
+    pub(crate) fn id(&self, player: usize) -> Option<u32> {
+        self.children[player].as_ref().map(Child::id)
+    }
+
+    /// Lets the process of the player run, when scheduling fairly
+    pub(crate) fn resume(&mut self, player: usize) {
+        if self.fair_scheduling {
+            self.signal(player, Signal::Continue);
+        }
+    }
+
+    /// Pauses the process of the player, when scheduling fairly
+    pub(crate) fn pause(&mut self, player: usize) {
+        if self.fair_scheduling {
+            self.signal(player, Signal::Stop);
+        }
+    }
+
+    /// Kills the process of the player and everything it started, or closes its connection
+    pub(crate) fn kill(&mut self, player: usize) {
+        self.signal(player, Signal::Kill);
+        if let Some(socket) = &self.sockets[player] {
+            socket.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Stops all processes and waits for them, so that no zombies are left behind. Processes
+    /// that have been sent `stop` get `grace_period` to exit by themselves, after which their
+    /// process groups are asked to terminate with SIGTERM. Whatever is still running after
+    /// another grace period is killed. Sockets are closed for reading right away, the writing
+    /// thread closes them for writing once it is done. Threads get both grace periods to end,
+    /// after which they are left running. Returns the exit status of every process,
+    /// `None` for sockets and scripts that could not be started.
+    pub(crate) fn shutdown(mut self, grace_period: Duration) -> Vec<Option<ExitStatus>> {
+        let players = 0..self.children.len();
+        for socket in self.sockets.iter().flatten() {
+            socket.shutdown(Shutdown::Read); // the writing thread may still be sending `stop`
+        }
+        for player in players.clone() {
+            self.resume(player); // a paused process can neither exit nor handle SIGTERM
+        }
+        self.wait_all(grace_period);
+        for player in players.clone() {
+            self.signal(player, Signal::Terminate);
+        }
+        self.wait_all(grace_period);
+        for player in players {
+            self.signal(player, Signal::Kill);
+        }
+        // reaped only now, after the last signal to their groups
+        self.children
+            .iter_mut()
+            .map(|child| child.as_mut().and_then(|child| child.wait().ok()))
+            .collect()
+    }
+
+    /// Waits until all processes have exited and all threads have ended, but at most `timeout`
+    fn wait_all(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while !self
+            .children
+            .iter_mut()
+            .flatten()
+            .all(|child| exit_status(child).is_some())
+            || !self.threads.iter().flatten().all(JoinHandle::is_finished)
+        {
+            if Instant::now() >= deadline {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// How the process exited, if it exits within `timeout`
+    pub(crate) fn wait_timeout(&mut self, player: usize, timeout: Duration) -> Option<Exit> {
+        let child = self.children[player].as_mut()?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = exit_status(child) {
+                let cpu_time = cpu_time(child.id());
+                return Some(Exit { status, cpu_time });
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Sends a signal to the process group of the player. The group outlives the script if it
+    /// started other processes. Its id cannot be reused while any of them is running, nor while
+    /// the script is not reaped, which only happens once nothing is sent to the group anymore, see
+    /// `exit_status`.
+    #[cfg(target_os = "linux")]
+    fn signal(&mut self, player: usize, signal: Signal) {
+        let Some(child) = self.children[player].as_mut() else {
+            return;
+        };
+        let signal = match signal {
+            Signal::Stop => libc::SIGSTOP,
+            Signal::Continue => libc::SIGCONT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // SAFETY: kill only sends a signal. The script was started as the leader of its own
+        // process group, so the group id is its process id
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), signal);
+        }
+    }
+
+    /// Without process groups, only killing the script itself is supported
+    #[cfg(not(target_os = "linux"))]
+    fn signal(&mut self, player: usize, signal: Signal) {
+        if let (Some(child), Signal::Kill) = (self.children[player].as_mut(), signal) {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// How a process exited
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Exit {
+    pub status: ExitStatus,
+    /// CPU time the process used, if it could be read
+    pub cpu_time: Option<Duration>,
+}
+
+/// Exit status of the process if it has exited, without reaping it. Until it is reaped at the
+/// end of `Processes::shutdown`, its process id stays taken, and with it the id of its process
+/// group, so that signals to the group cannot reach processes that took over the id. Its CPU
+/// time can also still be read.
+#[cfg(target_os = "linux")]
+fn exit_status(child: &mut Child) -> Option<ExitStatus> {
+    use std::os::unix::process::ExitStatusExt;
+    // SAFETY: siginfo_t is a plain C struct that waitid fills in
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: WNOWAIT leaves the process to be reaped by `Child::wait`
+    let exited = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            child.id(),
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        ) == 0
+            && info.si_pid() != 0
+    };
+    if !exited {
+        return None;
+    }
+    // SAFETY: the fields of a SIGCHLD are filled in, since the process exited
+    let status = unsafe { info.si_status() };
+    // back to the encoding of waitpid, which ExitStatus is built from
+    Some(ExitStatus::from_raw(match info.si_code {
+        libc::CLD_EXITED => (status & 0xff) << 8,
+        libc::CLD_DUMPED => status | 0x80,
+        _ => status,
+    }))
+}
+
+/// Without `waitid`, the process is reaped right away, and its CPU time is not known
+#[cfg(not(target_os = "linux"))]
+fn exit_status(child: &mut Child) -> Option<ExitStatus> {
+    child.try_wait().ok().flatten()
+}
+
+#[derive(Clone, Copy)]
+enum Signal {
+    Stop,
+    Continue,
+    Terminate,
+    Kill,
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::process::Command;
+
+    fn spawn(script: &str) -> Option<Child> {
+        Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .process_group(0)
+            .spawn()
+            .ok()
+    }
+
+    #[test]
+    fn shutdown() {
+        let children = vec![
+            spawn("exit 3"),
+            None,
+            // ignores SIGTERM, and starts a helper that outlives it
+            spawn("trap '' TERM; sleep 30 & sleep 30"),
+        ];
+        let group = children[2].as_ref().unwrap().id() as libc::pid_t;
+        let peers = children.into_iter().map(|c| c.map(Peer::Process)).collect();
+        let processes = Processes::new(peers, false);
+        let exit_statuses = processes.shutdown(Duration::from_millis(50));
+        assert_eq!(exit_statuses[0].and_then(|status| status.code()), Some(3));
+        assert_eq!(exit_statuses[1], None);
+        assert_eq!(
+            exit_statuses[2].and_then(|status| status.signal()),
+            Some(libc::SIGKILL)
+        );
+        // the helper was killed along with the script
+        thread::sleep(Duration::from_millis(50));
+        assert!(!group_is_running(group));
+    }
+
+    #[test]
+    fn exit() {
+        let peers = vec![spawn("exit 2").map(Peer::Process)];
+        let mut processes = Processes::new(peers, false);
+        let exit = processes.wait_timeout(0, Duration::from_secs(5)).unwrap();
+        assert_eq!(exit.status.code(), Some(2));
+        // read before the process was reaped
+        assert!(exit.cpu_time.is_some());
+        // not reaped before the shutdown, so that its process group id cannot be reused
+        let pid = processes.id(0).unwrap();
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        assert_eq!(
+            stat.rsplit_once(')').unwrap().1.split_whitespace().next(),
+            Some("Z")
+        );
+        let exit_statuses = processes.shutdown(Duration::ZERO);
+        assert_eq!(exit_statuses[0].and_then(|status| status.code()), Some(2));
+    }
+
+    /// Whether any process in the group is still running. Killed helpers are orphans, which may
+    /// linger as zombies until init reaps them, so those do not count
+    fn group_is_running(group: libc::pid_t) -> bool {
+        std::fs::read_dir("/proc").unwrap().flatten().any(|entry| {
+            let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+                return false;
+            };
+            // state and process group are the first and third field after the name
+            let fields = stat
+                .rsplit_once(')')
+                .map_or(vec![], |(_, rest)| rest.split_whitespace().collect());
+            fields.len() > 2 && fields[0] != "Z" && fields[2] == group.to_string()
+        })
+    }
+}
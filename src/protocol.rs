@@ -0,0 +1,345 @@
+use itertools::Itertools;
+
+use crate::game::{Direction, InvalidDirection};
+use crate::parse_instruction::{parse_usize_pair, Instruction};
+
+/// Version of the protocol spoken by the runner. Bots are spoken to in version 1, the protocol from
+/// before the handshake, unless they are given this version, see `BotSpec::protocol`.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// How long a bot may take to answer the handshake, which includes starting up
+pub(crate) const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Optional parts of the protocol, which the runner offers in the handshake and bots accept or
+/// not. In version 1, bots get those of `V1_FEATURES`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Feature {
+    /// `time_control:{initial}+{increment}` in the header and the remaining time with every move
+    /// request, when playing with a time control
+    TimeBank,
+    /// `topology:{name}` in the header, when not playing on a torus
+    Topology,
+    /// `walls:{x},{y} ...` in the header, when playing on a map with walls
+    Map,
+    /// All messages after the handshake are JSON objects instead of lines, see `Format::Json`
+    Json,
+    /// The whole board (see `GameState`) before every move request
+    FullState,
+}
+
+/// Features offered by the runner
+pub const FEATURES: [Feature; 5] = [
+    Feature::TimeBank,
+    Feature::Topology,
+    Feature::Map,
+    Feature::Json,
+    Feature::FullState,
+];
+
+/// Features of the protocol from before the handshake
+pub const V1_FEATURES: [Feature; 3] = [Feature::TimeBank, Feature::Topology, Feature::Map];
+
+impl Feature {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::TimeBank => "time_bank",
+            Self::Topology => "topology",
+            Self::Map => "map",
+            Self::Json => "json",
+            Self::FullState => "full_state",
+        }
+    }
+
+    /// The feature that the header line with this key belongs to, if any
+    fn of_header_key(key: &str) -> Option<Self> {
+        match key {
+            "time_control" => Some(Self::TimeBank),
+            "topology" => Some(Self::Topology),
+            "walls" => Some(Self::Map),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Unknown feature")]
+pub struct UnknownFeature;
+
+impl std::str::FromStr for Feature {
+    type Err = UnknownFeature;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FEATURES
+            .into_iter()
+            .find(|feature| feature.name() == s.trim())
+            .ok_or(UnknownFeature)
+    }
+}
+
+/// What the runner and a bot agreed on before the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handshake {
+    pub version: u32,
+    pub features: Vec<Feature>,
+    /// Name the bot gave itself in the handshake, if any
+    pub name: Option<String>,
+}
+
+impl Handshake {
+    /// Protocol version 1, for bots that are not greeted with the handshake
+    pub fn legacy() -> Self {
+        Self {
+            version: 1,
+            features: V1_FEATURES.to_vec(),
+            name: None,
+        }
+    }
+
+    pub fn accepts(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn format(&self) -> Format {
+        if self.accepts(Feature::Json) {
+            Format::Json
+        } else {
+            Format::Lines
+        }
+    }
+
+    /// The header of the game without the lines of features the bot did not accept
+    pub(crate) fn filter_header(&self, header: &str) -> String {
+        header
+            .lines()
+            .filter(|line| {
+                let key = line.split_once(':').map(|(key, _)| key);
+                key.and_then(Feature::of_header_key)
+                    .is_none_or(|feature| self.accepts(feature))
+            })
+            .join("\n")
+    }
+}
+
+/// First line the runner sends to a bot: `snakerunner:{version}:{features}`
+pub(crate) fn greeting() -> String {
+    format!(
+        "snakerunner:{PROTOCOL_VERSION}:{}",
+        FEATURES.iter().join(",")
+    )
+}
+
+/// Answer of a bot that speaks the current version and accepts those of `supported` that the
+/// runner offers in `greeting`. `None` if `greeting` is no greeting of the runner.
+pub(crate) fn reply_to(greeting: &str, supported: &[Feature]) -> Option<String> {
+    let offered = greeting.trim().strip_prefix("snakerunner:")?;
+    let features = offered
+        .split_once(':')
+        .map_or("", |(_, features)| features)
+        .split(',')
+        .filter_map(|feature| feature.parse::<Feature>().ok())
+        .filter(|feature| supported.contains(feature))
+        .join(",");
+    Some(format!("hello:{PROTOCOL_VERSION}:{features}:"))
+}
+
+/// Parses the answer of a bot to the greeting: `hello:{version}:{features}:{name}`, in which the
+/// features and the name may be empty. Features the runner did not offer are ignored, a bot
+/// answering with version 1 gets all of them. `None` if the line is no valid answer.
+pub(crate) fn parse_reply(line: &str) -> Option<Handshake> {
+    let mut fields = line.trim().splitn(4, ':');
+    if fields.next()? != "hello" {
+        return None;
+    }
+    let version: u32 = fields.next()?.parse().ok()?;
+    let features = fields
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|feature| feature.parse().ok())
+        .unique()
+        .collect();
+    let name = fields
+        .next()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    match version {
+        1 => Some(Handshake {
+            name,
+            ..Handshake::legacy()
+        }),
+        PROTOCOL_VERSION => Some(Handshake {
+            version,
+            features,
+            name,
+        }),
+        _ => None,
+    }
+}
+
+/// How messages are written after the handshake
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Format {
+    /// The line protocol: `{player}:{direction}`, `move`, ... and a direction as the answer
+    #[default]
+    Lines,
+    /// One JSON object per line, with the kind of message in `type`, e.g.
+    /// `{"type":"move","player":0,"direction":"N"}`, and `{"direction":"N"}` as the answer
+    Json,
+}
+
+/// Answer of a bot speaking JSON to a move request. Other fields, like `type`, are ignored.
+#[derive(serde::Deserialize)]
+struct JsonAnswer {
+    direction: Direction,
+}
+
+impl Format {
+    pub fn encode(&self, instruction: &Instruction) -> String {
+        match self {
+            Self::Lines => instruction.to_string(),
+            Self::Json => instruction.to_json(),
+        }
+    }
+
+    /// The header followed by the number of the player. In JSON, the header is a single object
+    /// of type `header`, with the extension lines as fields named after their key, e.g.
+    /// `{"type":"header","width":10,"height":10,"starting_positions":[[0,2],[9,6]],"topology":"box","player":1}`.
+    pub(crate) fn header(&self, header: &str, player: usize) -> String {
+        match self {
+            Self::Lines => format!("{header}\n{player}"),
+            Self::Json => json_header(header, player).to_string(),
+        }
+    }
+
+    /// Parses the answer of a bot to a move request
+    pub fn parse_answer(&self, line: &str) -> Result<Direction, InvalidDirection> {
+        match self {
+            Self::Lines => line.trim().parse(),
+            Self::Json => serde_json::from_str::<JsonAnswer>(line)
+                .map(|answer| answer.direction)
+                .map_err(|_| InvalidDirection),
+        }
+    }
+}
+
+fn json_header(header: &str, player: usize) -> serde_json::Value {
+    use serde_json::{json, Value};
+    let mut lines = header.lines();
+    let (width, height) = lines
+        .next()
+        .and_then(|line| parse_usize_pair(line).ok())
+        .unwrap_or_default();
+    let n_players: usize = lines
+        .next()
+        .and_then(|line| line.parse().ok())
+        .unwrap_or_default();
+    let starting_positions = lines
+        .by_ref()
+        .take(n_players)
+        .filter_map(|line| parse_usize_pair(line).ok())
+        .collect_vec();
+    let mut message = json!({
+        "type": "header",
+        "width": width,
+        "height": height,
+        "starting_positions": starting_positions,
+    });
+    for (key, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = match key {
+            "walls" => json!(value
+                .split_whitespace()
+                .filter_map(|wall| parse_usize_pair(wall).ok())
+                .collect_vec()),
+            _ => Value::from(value),
+        };
+        message[key] = value;
+    }
+    message["player"] = json!(player);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply() {
+        assert_eq!(
+            greeting(),
+            "snakerunner:2:time_bank,topology,map,json,full_state"
+        );
+        assert_eq!(
+            parse_reply("hello:2:map,fast,time_bank:deep 3"),
+            Some(Handshake {
+                version: 2,
+                features: vec![Feature::Map, Feature::TimeBank],
+                name: Some("deep 3".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_reply("hello:2"),
+            Some(Handshake {
+                version: 2,
+                features: vec![],
+                name: None,
+            })
+        );
+        assert_eq!(
+            parse_reply("hello:1::old:bot").map(|h| (h.version, h.name)),
+            Some((1, Some("old:bot".to_string())))
+        );
+        assert_eq!(parse_reply("hello:3:map"), None);
+        assert_eq!(
+            reply_to("snakerunner:3:map,json,teleporters", &V1_FEATURES).as_deref(),
+            Some("hello:2:map:")
+        );
+        assert_eq!(reply_to("10,10", &V1_FEATURES), None);
+        assert_eq!(parse_reply("N"), None);
+    }
+
+    #[test]
+    fn header_features() {
+        let header = "10,10\n2\n1,1\n5,5\ntopology:box\nwalls:3,3\ntime_control:1000+10";
+        let handshake = Handshake {
+            version: 2,
+            features: vec![Feature::Map],
+            name: None,
+        };
+        assert_eq!(
+            handshake.filter_header(header),
+            "10,10\n2\n1,1\n5,5\nwalls:3,3"
+        );
+        assert_eq!(Handshake::legacy().filter_header(header), header);
+    }
+
+    #[test]
+    fn json_format() {
+        let header = "10,10\n2\n1,1\n5,5\ntopology:box\nwalls:3,3 4,3\nrules:classic:4";
+        assert_eq!(
+            Format::Json.header(header, 1),
+            r#"{"height":10,"player":1,"rules":"classic:4","starting_positions":[[1,1],[5,5]],"topology":"box","type":"header","walls":[[3,3],[4,3]],"width":10}"#
+        );
+        assert_eq!(Format::Lines.header("5,5\n1\n0,0", 0), "5,5\n1\n0,0\n0");
+        assert_eq!(
+            Format::Json.encode(&Instruction::Out {
+                player: 1,
+                reason: None
+            }),
+            r#"{"type":"out","player":1}"#
+        );
+        assert_eq!(
+            Format::Json.parse_answer(r#"{"type":"move","direction":"E"}"#),
+            Ok(Direction::East)
+        );
+        assert_eq!(Format::Json.parse_answer("E"), Err(InvalidDirection));
+        assert_eq!(Format::Lines.parse_answer("E\n"), Ok(Direction::East));
+        assert_eq!(Handshake::legacy().format(), Format::Lines);
+    }
+}
@@ -0,0 +1,241 @@
+use itertools::Itertools;
+
+use crate::game::Direction;
+use crate::parse_instruction::Instruction;
+use crate::topology::Topology;
+
+/// A variant of the game. The runner only talks to the game through this trait, so new variants
+/// can be added without touching it.
+pub trait GameRules: std::fmt::Display {
+    /// Sets up a game. The runner picks the implementation from the `rules` of the setup; given a
+    /// setup of other rules, an implementation plays by its own rules with their defaults.
+    fn new(setup: &GameSetup) -> Self
+    where
+        Self: Sized;
+
+    /// Header describing the game, sent to the players and written to the log. Settings that
+    /// differ from the default game are appended as `key:value` lines, so the header of a default
+    /// game stays compatible with scripts that do not know about them.
+    fn setup_string(&self) -> String;
+
+    /// Moves a single player. If the move loses, the player is out of the game.
+    fn apply_move(&mut self, player: usize, direction: Direction) -> MoveOutcome;
+
+    /// Moves all given players at once, as a single turn. Returns the outcome for every move, in
+    /// the order the moves were given.
+    fn apply_simultaneous_moves(&mut self, moves: &[(usize, Direction)]) -> Vec<MoveOutcome>;
+
+    /// Takes a player out of the game for a reason other than a losing move, e.g. a timeout
+    fn eliminate(&mut self, player: usize);
+
+    /// Called by the runner after every full turn
+    fn end_turn(&mut self);
+
+    /// Called by the runner before the first turn and after every move (or turn, when moving
+    /// simultaneously). Returns instructions that have to be sent to all players and written to the
+    /// log, such as food spawning.
+    fn spawn_items(&mut self) -> Vec<Instruction> {
+        Vec::new()
+    }
+
+    /// Applies an instruction that was returned by `spawn_items`, for replaying a game from a log
+    fn apply_event(&mut self, _event: &Instruction) {}
+
+    fn is_alive(&self, player: usize) -> bool;
+
+    /// Whether the game has ended
+    fn is_over(&self) -> bool;
+
+    /// The winner of the game, if there is one. Only meaningful once the game is over.
+    fn winner(&self) -> Option<usize>;
+
+    /// Everything on the board, for bots that do not keep track of the game themselves
+    fn state(&self) -> GameState;
+}
+
+/// The complete state of a game, sent to bots that accept full-state updates. Written as a line
+/// like `state:3:0,1:1,1 5,5:.,.,. 0,#,1` (see `Instruction`): the turn, the players that are
+/// still alive, the head of every player and the rows of the board.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GameState {
+    /// Number of turns that have been played
+    pub turn: usize,
+    /// Players that are still in the game, in increasing order
+    pub alive: Vec<usize>,
+    /// Head of every player, including those that are out
+    pub heads: Vec<(usize, usize)>,
+    /// The rows of the board from top to bottom, `board[y][x]` is the cell at `(x, y)`
+    pub board: Vec<Vec<Cell>>,
+}
+
+impl std::fmt::Display for GameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.turn,
+            self.alive.iter().join(","),
+            self.heads.iter().map(|(x, y)| format!("{x},{y}")).join(" "),
+            self.board.iter().map(|row| row.iter().join(",")).join(" ")
+        )
+    }
+}
+
+/// Contents of a cell in a `GameState`: `.` when free, `#` for a wall, `*` for food, or the number
+/// of the player whose snake is on it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cell {
+    Free,
+    Wall,
+    Food,
+    Snake(usize),
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Expected ., #, * or a player number")]
+pub struct InvalidCell;
+
+impl std::str::FromStr for Cell {
+    type Err = InvalidCell;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "." => Ok(Self::Free),
+            "#" => Ok(Self::Wall),
+            "*" => Ok(Self::Food),
+            player => player.parse().map(Self::Snake).map_err(|_| InvalidCell),
+        }
+    }
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Free => write!(f, "."),
+            Self::Wall => write!(f, "#"),
+            Self::Food => write!(f, "*"),
+            Self::Snake(player) => write!(f, "{player}"),
+        }
+    }
+}
+
+/// Cells are written the same way in JSON, e.g. `"#"` or `"0"`
+impl serde::Serialize for Cell {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Cell {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveOutcome {
+    Moved,
+    /// Moved onto a snake or a wall
+    Crashed,
+    /// Moved off the board
+    LeftBoard,
+    /// Moved into the same cell as another player in the same turn
+    HeadOn,
+}
+
+impl MoveOutcome {
+    pub fn is_loss(&self) -> bool {
+        !matches!(self, Self::Moved)
+    }
+}
+
+/// Everything needed to set up a game
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSetup {
+    pub width: usize,
+    pub height: usize,
+    pub starting_positions: Vec<(usize, usize)>,
+    pub topology: Topology,
+    pub walls: Vec<(usize, usize)>,
+    pub rules: Ruleset,
+    /// End the game after this many turns
+    pub max_turns: Option<usize>,
+}
+
+/// Length of the snakes in classic games when none is given
+pub const DEFAULT_CLASSIC_LENGTH: usize = 3;
+
+/// Selects the implementation of `GameRules` by name
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Ruleset {
+    /// Snakes never shrink, every cell a snake has visited stays occupied. See `TorusSnakeGame`
+    #[default]
+    Tron,
+    /// Snakes grow until they reach a fixed length, after which their tail vacates a cell for every
+    /// move. Eating food makes a snake one cell longer. See `ClassicSnakeGame`
+    Classic { length: usize },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown ruleset, expected tron, classic or classic:{{length}}")]
+pub struct InvalidRuleset;
+
+impl std::str::FromStr for Ruleset {
+    type Err = InvalidRuleset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().split_once(':') {
+            None if s.trim().eq_ignore_ascii_case("tron") => Ok(Self::Tron),
+            None if s.trim().eq_ignore_ascii_case("classic") => Ok(Self::Classic {
+                length: DEFAULT_CLASSIC_LENGTH,
+            }),
+            Some(("classic", length)) => match length.parse() {
+                Ok(length) if length > 0 => Ok(Self::Classic { length }),
+                _ => Err(InvalidRuleset),
+            },
+            _ => Err(InvalidRuleset),
+        }
+    }
+}
+
+impl Ruleset {
+    /// Turn limit used when none is given. Classic games can go on forever, since snakes can
+    /// follow their own tail.
+    pub fn default_max_turns(&self) -> Option<usize> {
+        match self {
+            Self::Tron => None,
+            Self::Classic { .. } => Some(1000),
+        }
+    }
+}
+
+impl std::fmt::Display for Ruleset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tron => write!(f, "tron"),
+            Self::Classic { length } => write!(f, "classic:{length}"),
+        }
+    }
+}
+
+/// Draws the board with a border around it, using `display_cell` for the contents of every cell
+pub fn render_board(
+    width: usize,
+    height: usize,
+    display_cell: impl Fn((usize, usize)) -> String,
+) -> String {
+    let horizontal_border = format!("+{}+", "-".to_owned().repeat(width));
+    format!(
+        "{}\n{}{}",
+        horizontal_border.clone(),
+        (0..height)
+            .map(|y| format!(
+                "|{}|\n",
+                (0..width).map(|x| display_cell((x, y))).collect::<String>()
+            ))
+            .collect::<String>(),
+        horizontal_border
+    )
+}
@@ -1,122 +1,389 @@
-use anyhow::Result;
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::sync::mpsc;
+use std::process::{Child, ChildStderr, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{mpsc, Arc};
 // use std::sync::mpsc::Sender;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use rand::distributions::{Distribution, Uniform};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
+use crate::bot::{make_names_unique, BotSource, BotSpec};
+use crate::classic::ClassicSnakeGame;
+use crate::clock::{Clock, MoveLimit, TimeControl};
 use crate::game::{Direction, TorusSnakeGame};
+use crate::gamelog::{LogEntry, LogWriter};
+use crate::human;
+use crate::launcher::Launchers;
+use crate::map::Map;
+use crate::parse_instruction::{Instruction, OutReason};
+use crate::process::Processes;
+use crate::protocol::{self, Feature, Handshake, HANDSHAKE_TIMEOUT, PROTOCOL_VERSION};
+use crate::rules::{GameRules, GameSetup, GameState, Ruleset};
+use crate::sandbox::{ResourceLimit, Sandbox};
+use crate::strategy;
+use crate::topology::Topology;
+use crate::transport::{Connection, Socket, CONNECT_TIMEOUT};
 
 enum Message {
-    CommunicateMove { direction: Direction, player: usize },
-    AskMove(usize), // ask player to move
-    Kill(usize),
-    SendHeader(String),
-}
-
-fn make_process_python(program_name: &str) -> Child {
-    let python_command = if cfg!(windows) { "python" } else { "python3" };
-
-    let path = Path::new(program_name);
-    let mut dir = path.parent().unwrap_or(Path::new("./"));
-    if dir == Path::new("") {
-        // if no parent directory
-        dir = Path::new("./");
-    }
-    let filename = path.file_name().unwrap();
-    Command::new(python_command)
-        .current_dir(dir)
-        .arg("-m")
-        .arg(filename)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap()
+    CommunicateMove {
+        direction: Direction,
+        player: usize,
+    },
+    CommunicateTurn(Vec<(usize, Direction)>), // all moves made in one simultaneous turn
+    AskMove {
+        player: usize,
+        remaining_time: Option<u64>, // only sent when playing with a time control
+        state: Option<GameState>,    // sent before the request, to bots that accept full states
+    },
+    Kill {
+        player: usize,
+        reason: Option<OutReason>, // `None` for the winner
+    },
+    SendHeader {
+        header: String,
+        names: Vec<String>, // only logged
+        simultaneous: bool,
+    },
+    Broadcast(Instruction), // sent to all remaining players, e.g. spawned food
+    LogClock(Vec<(usize, u64)>), // remaining time of players after their move, only logged
+    LogTiming(Vec<(usize, u64, u64)>), // CPU and wall-clock time of the last move, only logged
 }
 
-fn make_process_exe(program_name: &str) -> Child {
-    Command::new(format!(r#"./{program_name}"#))
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap()
+/// Settings shared by every game in a run or match
+#[derive(Clone)]
+pub struct GameSettings {
+    pub width: usize,
+    pub height: usize,
+    /// Time limit for each move in milliseconds. First move gets 10x more time to allow for some setup.
+    /// Not used when playing with a time control.
+    pub time_limit: u64,
+    /// Give every player a bank of time for the whole game instead of a fixed time per move
+    pub time_control: Option<TimeControl>,
+    /// Apply the time limits to the CPU time the scripts use instead of wall-clock time, so that
+    /// a busy machine does not make them time out. Wall-clock time is still limited to 10x the
+    /// time limit. Only supported on Linux.
+    pub cpu_time: bool,
+    /// How scripts are started, depending on their name
+    pub launchers: Launchers,
+    /// Resource limits and isolation for the processes of the scripts
+    pub sandbox: Sandbox,
+    /// Pause the processes of all scripts except those that are asked for a move, so that scripts
+    /// cannot use CPU time while their opponents think. Only supported on Linux.
+    pub fair_scheduling: bool,
+    /// Time in milliseconds players get to exit after they have been sent `stop` at the end of
+    /// the game, before they are terminated with SIGTERM, and again before they are killed
+    pub grace_period: u64,
+    /// Ask all players for their move at the same time and resolve the turn as a whole, instead of
+    /// letting players move one after another
+    pub simultaneous: bool,
+    /// What happens when a snake moves over an edge of the board
+    pub topology: Topology,
+    /// Walls and starting positions. The size of the map has to match `width` and `height`
+    pub map: Option<Map>,
+    /// Whether snakes keep growing or have a fixed length and eat food
+    pub rules: Ruleset,
+    /// End the game after this many turns. The longest remaining snake wins, or nobody if several
+    /// are equally long
+    pub max_turns: Option<usize>,
+}
+
+/// Channels used by the main loop to communicate with the writing and reading threads
+struct PlayerChannels {
+    messages: mpsc::Sender<Message>,
+    listeners: Vec<mpsc::Sender<()>>, // one per player, requests the reading thread to read one line
+    lines: mpsc::Receiver<(usize, String)>,
+    broken_pipes: mpsc::Receiver<usize>, // players that could not be written to
+    protocols: Vec<Handshake>,           // what every player agreed on in the handshake
+}
+
+impl PlayerChannels {
+    fn send(&self, message: Message) -> Result<(), RunnerError> {
+        self.messages
+            .send(message)
+            .map_err(|_| RunnerError::WriterStopped)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunnerError {
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(#[from] ConfigError),
+    #[error("Could not write {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Communication with the scripts stopped unexpectedly")]
+    WriterStopped,
+}
+
+/// Settings that cannot be used to play a game
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ConfigError {
+    #[error("No scripts were given")]
+    NoScripts,
+    #[error("The board has no cells")]
+    EmptyBoard,
+    #[error("The map is {map_width}x{map_height}, but the board is {width}x{height}")]
+    MapSizeMismatch {
+        map_width: usize,
+        map_height: usize,
+        width: usize,
+        height: usize,
+    },
+    #[error("Map has starting positions for {available} players, but {needed} scripts were given")]
+    NotEnoughMapPositions { available: usize, needed: usize },
+    #[error("{given} starting positions were given for {needed} scripts")]
+    WrongNumberOfPositions { given: usize, needed: usize },
+    #[error("Starting position {x},{y} is not on the board")]
+    PositionOutsideBoard { x: usize, y: usize },
+    #[error("Starting position {x},{y} is a wall or the starting position of another player")]
+    PositionBlocked { x: usize, y: usize },
+    #[error("The board has {free_cells} free cells, which is not enough for {needed} players")]
+    BoardTooSmall { free_cells: usize, needed: usize },
+    #[error("Measuring CPU time is only supported on Linux")]
+    CpuTimeUnsupported,
+    #[error("Resource limits and isolation are only supported on Linux")]
+    SandboxUnsupported,
+    #[error("Fair scheduling is only supported on Linux")]
+    FairSchedulingUnsupported,
 }
 
-fn make_process(filename: &str) -> Child {
-    if let Some(program_name) = filename.strip_suffix(".py") {
-        make_process_python(program_name)
-    } else {
-        make_process_exe(filename)
+/// Starts the process of the bot, or connects to its socket
+fn connect(bot: &BotSpec, settings: &GameSettings) -> io::Result<Connection> {
+    match &bot.source {
+        BotSource::Tcp(address) => Connection::socket(Socket::tcp(address)?),
+        BotSource::Unix(path) => Connection::socket(Socket::unix(path)?),
+        BotSource::Lobby(lobby) => Connection::socket(lobby.take(&bot.name, CONNECT_TIMEOUT)?),
+        &BotSource::Human { time_limit } => Connection::thread(move |input, output| {
+            // if the human cannot play, their output ends and they lose like a bot that stopped
+            let _ = human::play(input, output, time_limit);
+        }),
+        BotSource::InProcess(factory) => {
+            let factory = factory.clone();
+            Connection::thread(move |input, output| {
+                // like a bot that stops, a strategy whose input cannot be read loses
+                let _ = strategy::play(input, output, &mut *factory.create());
+            })
+        }
+        BotSource::Script(_) | BotSource::Command { .. } => {
+            make_process(bot, settings).map(Connection::process)
+        }
     }
+}
+
+/// Connects to the bot and agrees on the protocol with it. Only bots that speak version 2 are
+/// greeted with the handshake, and cannot play if they do not answer it. All other bots get
+/// exactly what they got before the handshake.
+fn connect_with_handshake(
+    bot: &BotSpec,
+    settings: &GameSettings,
+) -> io::Result<(Connection, Handshake)> {
+    let mut connection = connect(bot, settings)?;
+    if bot.protocol != Some(PROTOCOL_VERSION) {
+        return Ok((connection, Handshake::legacy()));
+    }
+    match handshake(&mut connection) {
+        Some(handshake) => Ok((connection, handshake)),
+        None => {
+            close(connection);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "did not answer the handshake",
+            ))
+        }
+    }
+}
+
+/// Greets the bot and waits for its answer. `None` if it does not answer in time, or answers
+/// something else
+fn handshake(connection: &mut Connection) -> Option<Handshake> {
+    writeln!(connection.input, "{}", protocol::greeting()).ok()?;
+    connection.input.flush().ok()?;
+    // read in another thread, so that we can stop waiting for a bot that does not answer. Closing
+    // the connection ends the read
+    let mut output = std::mem::replace(&mut connection.output, Box::new(io::empty()));
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        let read = output.read_line(&mut line);
+        let _ = sender.send((read.map(|_| line), output));
+    });
+    let (line, output) = receiver.recv_timeout(HANDSHAKE_TIMEOUT).ok()?;
+    connection.output = output;
+    protocol::parse_reply(&line.ok()?)
+}
+
+/// Stops a bot that is not going to play on this connection
+fn close(connection: Connection) {
+    drop(connection.input);
+    Processes::new(vec![Some(connection.peer)], false).shutdown(Duration::ZERO);
+}
 
-    // else if let Some(program_name) = filename.strip_suffix(".exe") {
-    //     make_process_exe(program_name)
-    // } else {
-    //     panic!()
-    // }
+fn make_process(bot: &BotSpec, settings: &GameSettings) -> io::Result<Child> {
+    let mut command = bot.command(&settings.launchers)?;
+    // in its own process group, so that it can be stopped along with the processes it starts
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    settings.sandbox.apply(&mut command)?;
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
 }
 
 fn write_to_player(
     message: &str,
     player: usize,
-    stdin: &mut ChildStdin,
-    _sender: &mpsc::Sender<usize>,
+    stdin: &mut Option<Box<dyn Write + Send>>,
+    sender: &mpsc::Sender<usize>,
     alive_players: &mut HashSet<usize>,
     verbose: bool,
 ) {
     if verbose {
         println!("->p{player}  \"{message}\"")
     }
-    stdin
-        .write_all(format!("{message}\n").as_bytes())
-        .unwrap_or_else(|_| {
-            alive_players.remove(&player);
-            // let _ = sender.send(player); // Not much we can do if this fails
-        })
+    let written = stdin
+        .as_mut()
+        .is_some_and(|stdin| stdin.write_all(format!("{message}\n").as_bytes()).is_ok());
+    // report the player to the main loop, which takes it out of the game. Only once, and not for
+    // players that are already out
+    if !written && alive_players.remove(&player) {
+        let _ = sender.send(player); // Not much we can do if this fails
+    }
 }
 
-fn log(message: &str, writer_opt: &mut Option<LineWriter<File>>) -> Result<(), std::io::Error> {
-    if let Some(writer) = writer_opt {
-        writer.write_fmt(format_args!("{message}\n"))
-    } else {
-        Ok(())
+/// File to which the stderr of a player is written: `<log>.p<N>.stderr`
+pub fn stderr_path(log_filename: &Path, player: usize) -> PathBuf {
+    let mut path = log_filename.as_os_str().to_owned();
+    path.push(format!(".p{player}.stderr"));
+    path.into()
+}
+
+/// Copies the stderr of a player to `file` line by line, tagging every line with the time since
+/// the start of the game and the current turn. Without a file, stderr is only read, so that the
+/// player does not block on a full pipe. Writing is best effort, a debug log that cannot be
+/// written does not affect the game.
+fn capture_stderr(
+    stderr: ChildStderr,
+    mut file: Option<LineWriter<File>>,
+    game_start: Instant,
+    turn: &AtomicUsize,
+) {
+    let mut reader = BufReader::new(stderr);
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer) {
+            Ok(0) | Err(_) => break, // player has stopped
+            Ok(_) => {}
+        }
+        if let Some(writer) = file.as_mut() {
+            let _ = writeln!(
+                writer,
+                "[{:.3}s turn {}] {}",
+                game_start.elapsed().as_secs_f64(),
+                turn.load(Ordering::Relaxed),
+                String::from_utf8_lossy(&buffer).trim_end()
+            );
+        }
     }
 }
 
-fn random_starting_positions(width: usize, height: usize, n_players: usize) -> Vec<(usize, usize)> {
+fn random_starting_positions(
+    width: usize,
+    height: usize,
+    n_players: usize,
+    blocked: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
     let mut starting_positions = Vec::new();
     let x_sampler = Uniform::new(0, width);
     let y_sampler = Uniform::new(0, height);
     while starting_positions.len() < n_players {
-        // TODO if n_players too high, this loop can run forever
         let start_pos = (
             x_sampler.sample(&mut rand::thread_rng()),
             y_sampler.sample(&mut rand::thread_rng()),
         );
-        if !starting_positions.contains(&start_pos) {
+        if !starting_positions.contains(&start_pos) && !blocked.contains(&start_pos) {
             starting_positions.push(start_pos);
         }
     }
     starting_positions
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum LossReason {
     LosingMove,
     TimeOut,
     InvalidInput,
+    TurnLimit, // not the longest snake when the turn limit was reached
+    /// The process exited before the game was over
+    Crashed {
+        exit_status: ExitStatus,
+    },
+    /// Stdout was closed while the process kept running
+    ClosedStdout,
+    /// Stdin was closed while the process kept running
+    BrokenPipe,
+    /// The script could not be started
+    SpawnFailed {
+        error: String,
+    },
+    /// The process was stopped for using more of a resource than the sandbox allows
+    LimitExceeded {
+        limit: ResourceLimit,
+    },
 }
 
-#[derive(Clone)]
+impl LossReason {
+    /// Whether the loss points to a problem with the script rather than a tactical loss
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::LosingMove | Self::TurnLimit)
+    }
+
+    /// The reason as told to the bots and written to the log, without the details
+    pub fn out_reason(&self) -> OutReason {
+        match self {
+            Self::LosingMove => OutReason::LosingMove,
+            Self::TimeOut => OutReason::TimeOut,
+            Self::InvalidInput => OutReason::InvalidInput,
+            Self::TurnLimit => OutReason::TurnLimit,
+            Self::Crashed { .. } => OutReason::Crashed,
+            Self::ClosedStdout => OutReason::ClosedStdout,
+            Self::BrokenPipe => OutReason::BrokenPipe,
+            Self::SpawnFailed { .. } => OutReason::SpawnFailed,
+            Self::LimitExceeded { .. } => OutReason::LimitExceeded,
+        }
+    }
+}
+
+impl std::fmt::Display for LossReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LosingMove => write!(f, "made a losing move"),
+            Self::TimeOut => write!(f, "timed out"),
+            Self::InvalidInput => write!(f, "sent invalid input"),
+            Self::TurnLimit => write!(f, "did not win when the turn limit was reached"),
+            Self::Crashed { exit_status } => write!(f, "crashed ({exit_status})"),
+            Self::ClosedStdout => write!(f, "closed its stdout"),
+            Self::BrokenPipe => write!(f, "stopped reading its stdin"),
+            Self::SpawnFailed { error } => write!(f, "could not be started ({error})"),
+            Self::LimitExceeded { limit } => write!(f, "exceeded its {limit} limit"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum PlayerResult {
     Winner,
     Loser(LossReason),
@@ -131,253 +398,1016 @@ impl std::convert::From<PlayerStatus> for PlayerResult {
     }
 }
 
-impl PlayerResult {
-    fn is_winner(&self) -> bool {
-        match self {
-            Self::Winner => true,
-            _ => false,
-        }
-    }
-}
-
 #[derive(Clone)]
-pub enum PlayerStatus {
+enum PlayerStatus {
     Alive,
     Dead(LossReason),
 }
 
+/// Outcome of a single game
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResult {
+    /// `None` if the game ended without a winner, e.g. when the last players went out in the same
+    /// turn or several players survived until the turn limit
+    pub winner: Option<usize>,
+    /// Result of every player, in the order the bots were given
+    pub player_results: Vec<PlayerResult>,
+    /// How the process of every player exited, `None` if the script could not be started
+    pub exit_statuses: Vec<Option<ExitStatus>>,
+    /// What every player agreed on in the handshake, `None` if the bot could not be started
+    pub handshakes: Vec<Option<Handshake>>,
+}
+
 impl PlayerStatus {
     fn is_alive(&self) -> bool {
-        match self {
-            Self::Alive => true,
-            _ => false,
+        matches!(self, Self::Alive)
+    }
+}
+
+impl GameSettings {
+    /// Checks whether a game between `n_players` players can be played with these settings
+    pub fn validate(
+        &self,
+        n_players: usize,
+        starting_positions: Option<&[(usize, usize)]>,
+    ) -> Result<(), ConfigError> {
+        if n_players == 0 {
+            return Err(ConfigError::NoScripts);
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err(ConfigError::EmptyBoard);
+        }
+        if self.cpu_time && !cfg!(target_os = "linux") {
+            return Err(ConfigError::CpuTimeUnsupported);
+        }
+        if !self.sandbox.is_empty() && !cfg!(target_os = "linux") {
+            return Err(ConfigError::SandboxUnsupported);
+        }
+        if self.fair_scheduling && !cfg!(target_os = "linux") {
+            return Err(ConfigError::FairSchedulingUnsupported);
+        }
+        let walls = self.map.as_ref().map_or(&[][..], |map| &map.walls[..]);
+        if let Some(map) = &self.map {
+            if (map.width, map.height) != (self.width, self.height) {
+                return Err(ConfigError::MapSizeMismatch {
+                    map_width: map.width,
+                    map_height: map.height,
+                    width: self.width,
+                    height: self.height,
+                });
+            }
+            let available = map.starting_positions.len();
+            if starting_positions.is_none() && available != 0 && available < n_players {
+                return Err(ConfigError::NotEnoughMapPositions {
+                    available,
+                    needed: n_players,
+                });
+            }
+        }
+
+        let Some(starting_positions) = starting_positions else {
+            let free_cells = self.width * self.height - walls.len();
+            if free_cells < n_players {
+                return Err(ConfigError::BoardTooSmall {
+                    free_cells,
+                    needed: n_players,
+                });
+            }
+            return Ok(());
+        };
+        if starting_positions.len() != n_players {
+            return Err(ConfigError::WrongNumberOfPositions {
+                given: starting_positions.len(),
+                needed: n_players,
+            });
+        }
+        for (idx, &(x, y)) in starting_positions.iter().enumerate() {
+            if x >= self.width || y >= self.height {
+                return Err(ConfigError::PositionOutsideBoard { x, y });
+            }
+            if walls.contains(&(x, y)) || starting_positions[..idx].contains(&(x, y)) {
+                return Err(ConfigError::PositionBlocked { x, y });
+            }
+        }
+        Ok(())
+    }
+
+    /// Setup for a single game with the given starting positions
+    pub fn game_setup(&self, starting_positions: Vec<(usize, usize)>) -> GameSetup {
+        GameSetup {
+            width: self.width,
+            height: self.height,
+            starting_positions,
+            topology: self.topology,
+            walls: self
+                .map
+                .as_ref()
+                .map_or_else(Vec::new, |map| map.walls.clone()),
+            rules: self.rules,
+            max_turns: self.max_turns,
         }
     }
 }
 
-pub fn play_game(
-    scripts: &Vec<&str>,
-    starting_config: Option<Vec<(usize, usize)>>,
+/// Sets up games and matches between scripts. Settings that are not given default to those of the
+/// command line tool: a 10x10 torus, 100ms per move and the tron rules.
+///
+/// ```no_run
+/// use snakerunner::{Runner, Topology};
+///
+/// let result = Runner::new(["randommover.py", "unidirectionalmover.py"])
+///     .size(15, 15)
+///     .topology(Topology::Box)
+///     .log_file("log.txt")
+///     .play_game()?;
+/// println!("{:?}", result.winner);
+/// # Ok::<(), snakerunner::running::RunnerError>(())
+/// ```
+#[derive(Clone)]
+pub struct Runner {
+    bots: Vec<BotSpec>,
     width: usize,
     height: usize,
-    log_filename: Option<&Path>,
-    verbose: bool,
     time_limit: u64,
-) -> Option<(usize, Vec<PlayerResult>)> {
-    // TODO make output a result
-    let logfile = log_filename.map(|filename| File::create(filename).unwrap());
-    let writer = logfile.map(LineWriter::new);
+    time_control: Option<TimeControl>,
+    cpu_time: bool,
+    launchers: Launchers,
+    sandbox: Sandbox,
+    fair_scheduling: bool,
+    grace_period: u64,
+    simultaneous: bool,
+    topology: Topology,
+    map: Option<Map>,
+    rules: Ruleset,
+    max_turns: Option<usize>,
+    starting_positions: Option<Vec<(usize, usize)>>,
+    log_file: Option<PathBuf>,
+    verbose: bool,
+}
+
+impl Runner {
+    /// Runner for games between the bots. Bots can be given as the file names of scripts, or with
+    /// a name and command, see `BotSpec`. Bots that share a name are numbered to tell them apart.
+    pub fn new<B: Into<BotSpec>>(bots: impl IntoIterator<Item = B>) -> Self {
+        let mut bots = bots.into_iter().map(Into::into).collect_vec();
+        make_names_unique(&mut bots);
+        Self {
+            bots,
+            width: 10,
+            height: 10,
+            time_limit: 100,
+            time_control: None,
+            cpu_time: false,
+            launchers: Launchers::default(),
+            sandbox: Sandbox::default(),
+            fair_scheduling: false,
+            grace_period: 200,
+            simultaneous: false,
+            topology: Topology::Torus,
+            map: None,
+            rules: Ruleset::Tron,
+            max_turns: None,
+            starting_positions: None,
+            log_file: None,
+            verbose: false,
+        }
+    }
+
+    /// Size of the board. Ignored if a map is given
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
 
-    let n_players = scripts.len();
+    /// Time limit for each move in milliseconds. First move gets 10x more time to allow for some
+    /// setup.
+    pub fn time_limit(mut self, time_limit: u64) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
 
-    let starting_positions =
-        starting_config.unwrap_or_else(|| random_starting_positions(width, height, n_players));
+    /// Play with a chess clock instead of a fixed time limit per move. Every player starts with
+    /// `initial` milliseconds and gets `increment` more after every move it makes.
+    pub fn time_control(mut self, time_control: TimeControl) -> Self {
+        self.time_control = Some(time_control);
+        self
+    }
+
+    /// Limit the CPU time of the scripts instead of wall-clock time, see `GameSettings::cpu_time`
+    pub fn cpu_time(mut self, cpu_time: bool) -> Self {
+        self.cpu_time = cpu_time;
+        self
+    }
+
+    /// How scripts are started. Defaults to the built-in launchers, see `Launchers::default`
+    pub fn launchers(mut self, launchers: Launchers) -> Self {
+        self.launchers = launchers;
+        self
+    }
+
+    /// Resource limits and isolation for the processes of the scripts
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Only let scripts run while they are asked for a move, see `GameSettings::fair_scheduling`
+    pub fn fair_scheduling(mut self, fair_scheduling: bool) -> Self {
+        self.fair_scheduling = fair_scheduling;
+        self
+    }
+
+    /// Time in milliseconds scripts get to exit at the end of the game, see
+    /// `GameSettings::grace_period`
+    pub fn grace_period(mut self, grace_period: u64) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    pub fn simultaneous(mut self, simultaneous: bool) -> Self {
+        self.simultaneous = simultaneous;
+        self
+    }
+
+    pub fn topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Walls and starting positions. The map also determines the size of the board
+    pub fn map(mut self, map: Map) -> Self {
+        self.map = Some(map);
+        self
+    }
+
+    pub fn rules(mut self, rules: Ruleset) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Turn limit. Defaults to the limit of the ruleset, see `Ruleset::default_max_turns`
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// Starting positions for `play_game`, ordered by player. Chosen randomly (or taken from the
+    /// map) if not given. Matches always use random starting positions.
+    pub fn starting_positions(mut self, starting_positions: Vec<(usize, usize)>) -> Self {
+        self.starting_positions = Some(starting_positions);
+        self
+    }
+
+    /// File to which `play_game` logs the game
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Print all inputs to and outputs from players, as well as the board at every move
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn settings(&self) -> GameSettings {
+        GameSettings {
+            width: self.map.as_ref().map_or(self.width, |map| map.width),
+            height: self.map.as_ref().map_or(self.height, |map| map.height),
+            time_limit: self.time_limit,
+            time_control: self.time_control,
+            cpu_time: self.cpu_time,
+            launchers: self.launchers.clone(),
+            sandbox: self.sandbox.clone(),
+            fair_scheduling: self.fair_scheduling,
+            grace_period: self.grace_period,
+            simultaneous: self.simultaneous,
+            topology: self.topology,
+            map: self.map.clone(),
+            rules: self.rules,
+            max_turns: self.max_turns.or(self.rules.default_max_turns()),
+        }
+    }
+
+    /// The bots in order of player, with unique names
+    pub fn bots(&self) -> &[BotSpec] {
+        &self.bots
+    }
+
+    /// Plays a single game between the bots
+    pub fn play_game(&self) -> Result<GameResult, RunnerError> {
+        let settings = self.settings();
+        let starting_positions = self.starting_positions.clone();
+        let log_file = self.log_file.as_deref();
+        match self.rules {
+            Ruleset::Tron => play_game::<TorusSnakeGame>(
+                &self.bots,
+                starting_positions,
+                &settings,
+                log_file,
+                self.verbose,
+            ),
+            Ruleset::Classic { .. } => play_game::<ClassicSnakeGame>(
+                &self.bots,
+                starting_positions,
+                &settings,
+                log_file,
+                self.verbose,
+            ),
+        }
+    }
+
+    /// Plays a match of `n_games` games and returns the player that won most of them. Writes the
+    /// number of wins of every player to `summary_file` and, if given, the log of every game to
+    /// `logs_folder`.
+    pub fn play_match(
+        &self,
+        n_games: usize,
+        summary_file: &Path,
+        logs_folder: Option<&Path>,
+    ) -> Result<usize, RunnerError> {
+        let settings = self.settings();
+        match self.rules {
+            Ruleset::Tron => play_match::<TorusSnakeGame>(
+                &self.bots,
+                &settings,
+                n_games,
+                summary_file,
+                logs_folder,
+            ),
+            Ruleset::Classic { .. } => play_match::<ClassicSnakeGame>(
+                &self.bots,
+                &settings,
+                n_games,
+                summary_file,
+                logs_folder,
+            ),
+        }
+    }
+}
+
+/// Plays a single game between the bots with the rules given by `G`. Fails if the settings are
+/// invalid or the log cannot be written. Bots that cannot be started or misbehave during the
+/// game lose, but do not cause an error.
+pub fn play_game<G: GameRules>(
+    bots: &[BotSpec],
+    starting_config: Option<Vec<(usize, usize)>>,
+    settings: &GameSettings,
+    log_filename: Option<&Path>,
+    verbose: bool,
+) -> Result<GameResult, RunnerError> {
+    let n_players = bots.len();
+    settings.validate(n_players, starting_config.as_deref())?;
+
+    let writer = log_filename
+        .map(|path| {
+            LogWriter::create(path).map_err(|source| RunnerError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        })
+        .transpose()?;
+
+    // debug output of the players goes next to the log
+    let stderr_files = match log_filename {
+        Some(log_filename) => (0..n_players)
+            .map(|player| {
+                let path = stderr_path(log_filename, player);
+                File::create(&path)
+                    .map(|file| Some(LineWriter::new(file)))
+                    .map_err(|source| RunnerError::Io { path, source })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (0..n_players).map(|_| None).collect(),
+    };
+
+    let starting_positions = starting_config.unwrap_or_else(|| match &settings.map {
+        Some(map) if map.starting_positions.len() >= n_players => {
+            map.starting_positions[..n_players].to_vec()
+        }
+        Some(map) => {
+            random_starting_positions(settings.width, settings.height, n_players, &map.walls)
+        }
+        None => random_starting_positions(settings.width, settings.height, n_players, &[]),
+    });
 
     let mut player_statuses = vec![PlayerStatus::Alive; n_players];
 
-    let mut game = TorusSnakeGame::new(width, height, starting_positions);
+    let mut game = G::new(&settings.game_setup(starting_positions));
 
-    let mut children: Vec<Child> = scripts
-        .into_iter()
-        .map(|script_name| make_process(script_name.as_ref()))
-        .collect();
-    let stdins: Vec<_> = children
-        .iter_mut()
-        .map(|child| child.stdin.take().expect("Child has no stdin"))
-        .collect();
+    // all bots start up at the same time, so that waiting for their handshakes adds up less
+    let connected = thread::scope(|scope| {
+        let handles = bots
+            .iter()
+            .map(|bot| scope.spawn(|| connect_with_handshake(bot, settings)))
+            .collect_vec();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("connecting panicked")))
+            })
+            .collect_vec()
+    });
+    // players whose bot cannot be started or connected to are out before the first move
+    let mut connections = Vec::with_capacity(n_players);
+    let mut handshakes = Vec::with_capacity(n_players);
+    for (player, connected) in connected.into_iter().enumerate() {
+        match connected {
+            Ok((connection, handshake)) => {
+                if verbose {
+                    println!(
+                        "Player {player} speaks protocol version {} with features [{}]{}",
+                        handshake.version,
+                        handshake.features.iter().join(", "),
+                        handshake
+                            .name
+                            .as_ref()
+                            .map(|name| format!(" and calls itself {name}"))
+                            .unwrap_or_default()
+                    );
+                }
+                connections.push(Some(connection));
+                handshakes.push(Some(handshake));
+            }
+            Err(err) => {
+                player_statuses[player] = PlayerStatus::Dead(LossReason::SpawnFailed {
+                    error: err.to_string(),
+                });
+                connections.push(None);
+                handshakes.push(None);
+            }
+        }
+    }
 
-    let mut readers: Vec<BufReader<ChildStdout>> = children
-        .iter_mut()
-        .map(|child| BufReader::new(child.stdout.take().expect("Child has no stdout")))
-        .collect();
+    let game_start = Instant::now();
+    let turn = Arc::new(AtomicUsize::new(1));
+    let mut stdins = Vec::with_capacity(n_players);
+    let mut readers = Vec::with_capacity(n_players);
+    let mut peers = Vec::with_capacity(n_players);
+    for (connection, file) in connections.into_iter().zip(stderr_files) {
+        let Some(connection) = connection else {
+            stdins.push(None);
+            readers.push(None);
+            peers.push(None);
+            continue;
+        };
+        if let Some(stderr) = connection.stderr {
+            let turn = Arc::clone(&turn);
+            thread::spawn(move || capture_stderr(stderr, file, game_start, &turn));
+        }
+        stdins.push(Some(connection.input));
+        readers.push(Some(connection.output));
+        peers.push(Some(connection.peer));
+    }
 
     let (read_sender, read_receiver) = mpsc::channel();
-    let (write_sender, _write_receiver) = mpsc::channel(); // for now just used to kill child scripts
+    let (write_sender, write_receiver) = mpsc::channel(); // players whose stdin is closed
+
+    // players that could not be started are not written to, so what they would have agreed on
+    // does not matter
+    let protocols = handshakes
+        .iter()
+        .map(|handshake| handshake.clone().unwrap_or_else(Handshake::legacy))
+        .collect_vec();
 
     // thread for writing IO
-    thread::spawn(move || {
+    let writer_protocols = protocols.clone();
+    let writer_thread = thread::spawn(move || {
         writing_process(
             n_players,
             read_receiver,
             writer,
             stdins,
+            writer_protocols,
             write_sender,
             verbose,
-        );
+        )
     });
 
-    let (listener_sender, listener_receiver) = mpsc::channel(); // determines which child the thread will try to read from
     let (readline_sender, readline_receiver) = mpsc::channel();
 
-    // thread for reading from IO
-    thread::spawn(move || {
-        for player in listener_receiver {
-            // receives which player to read from
-            let mut buffer = String::new();
-            let reader: &mut BufReader<ChildStdout> = &mut readers[player]; // why do we need type annotation?
-            let _ = reader.read_line(&mut buffer); //error handling? If it fails, it's probably players fault, so just return the empty buffer and let outer loop kill them
-            let _ = readline_sender.send(buffer); // this appears to only fail when player times out
-                                                  // apparently it still sends its output (even though the process is explicitly killed) but function has quit
-                                                  // so channel is closed. Maybe .kill() is slow?
-        }
-    });
+    // threads for reading from IO, one per player so that players can be asked for moves in parallel
+    let listener_senders = readers
+        .into_iter()
+        .enumerate()
+        .map(|(player, reader)| {
+            let (listener_sender, listener_receiver) = mpsc::channel(); // every message requests one line from the player
+            let readline_sender = readline_sender.clone();
+            let Some(mut reader) = reader else {
+                return listener_sender; // never started, so there is nothing to read
+            };
+            thread::spawn(move || {
+                for () in listener_receiver {
+                    let mut buffer = String::new();
+                    let _ = reader.read_line(&mut buffer); //error handling? If it fails, it's probably players fault, so just return the empty buffer and let outer loop kill them
+                                                           // an empty buffer means the end of stdout was reached, a line always ends in a newline
+                    if readline_sender.send((player, buffer)).is_err() {
+                        // game is over, nobody is listening anymore
+                        break;
+                    }
+                }
+            });
+            listener_sender
+        })
+        .collect();
+
+    let mut processes = Processes::new(peers, settings.fair_scheduling);
 
-    // let mut attempts = 0;
-    // let mut alive_players: HashSet<usize> = (0..n_players).collect();
+    let channels = PlayerChannels {
+        messages: read_sender,
+        listeners: listener_senders,
+        lines: readline_receiver,
+        broken_pipes: write_receiver,
+        protocols,
+    };
 
+    let mut header = game.setup_string();
+    if let Some(time_control) = settings.time_control {
+        header.push_str(&format!("\ntime_control:{time_control}"));
+    }
     if verbose {
-        println!("{}", game.setup_string());
+        println!("{header}");
+    }
+    let played = channels
+        .send(Message::SendHeader {
+            header,
+            names: bots.iter().map(|bot| bot.name.clone()).collect(),
+            simultaneous: settings.simultaneous,
+        })
+        .and_then(|()| {
+            play_turns(
+                &mut game,
+                settings,
+                &channels,
+                &mut processes,
+                &mut player_statuses,
+                &bots.iter().map(BotSpec::move_limit).collect_vec(),
+                &turn,
+                verbose,
+            )
+        });
+    if let Err(err) = played {
+        processes.shutdown(Duration::ZERO);
+        return Err(err);
     }
-    read_sender
-        .send(Message::SendHeader(game.setup_string()))
-        .unwrap();
 
-    let mut first_loop = true;
+    let winner = game.winner();
+    for (i, status) in player_statuses.iter_mut().enumerate() {
+        if status.is_alive() {
+            let mut reason = None;
+            if Some(i) != winner {
+                // still in the game, but the game ended without them winning (e.g. turn limit)
+                *status = PlayerStatus::Dead(LossReason::TurnLimit);
+                reason = Some(OutReason::TurnLimit);
+            }
+            let _ = channels.send(Message::Kill { player: i, reason }); // kill remaining players
+                                                                        // TODO because kill_player takes a LossReason, we cannot use it to kill the winner.
+                                                                        // change kill_player, or leave as an exception like this?
+        }
+    }
+
+    // the writing thread stops once all messages are handled, closing the stdin of every player.
+    // Players that do not exit by themselves are stopped, which also unblocks the writing thread
+    // if it is stuck writing to a player that does not read. After that we know whether the log
+    // was written completely
+    drop(channels);
+    let exit_statuses = processes.shutdown(Duration::from_millis(settings.grace_period));
+    match writer_thread.join() {
+        Ok(Ok(())) => {}
+        Ok(Err(source)) => {
+            return Err(RunnerError::Io {
+                path: log_filename.map(Path::to_path_buf).unwrap_or_default(),
+                source,
+            })
+        }
+        Err(_) => return Err(RunnerError::WriterStopped),
+    }
+
+    let player_results = player_statuses
+        .into_iter()
+        .map(PlayerResult::from)
+        .collect_vec();
+
+    Ok(GameResult {
+        winner,
+        player_results,
+        exit_statuses,
+        handshakes,
+    })
+}
+
+/// Plays turns until the game is over
+#[allow(clippy::too_many_arguments)]
+fn play_turns<G: GameRules>(
+    game: &mut G,
+    settings: &GameSettings,
+    channels: &PlayerChannels,
+    processes: &mut Processes,
+    player_statuses: &mut [PlayerStatus],
+    move_limits: &[MoveLimit],
+    turn: &AtomicUsize,
+    verbose: bool,
+) -> Result<(), RunnerError> {
+    let n_players = player_statuses.len();
+    for (player, status) in player_statuses.iter().enumerate() {
+        if let PlayerStatus::Dead(reason) = status {
+            // could not be started
+            game.eliminate(player);
+            channels.send(Message::Kill {
+                player,
+                reason: Some(reason.out_reason()),
+            })?;
+        }
+    }
+    broadcast_items(game, channels)?;
+
+    let mut clock = Clock::new(settings, n_players);
+    for (player, &limit) in move_limits.iter().enumerate() {
+        clock.set_move_limit(player, limit);
+    }
     'mainloop: loop {
         if verbose {
             println!(
                 "\nRemaining players: {:?}",
                 (0..n_players)
-                    .filter(|player| player_statuses[*player].is_alive())
+                    .filter(|player| game.is_alive(*player))
                     .collect_vec()
             );
         }
-        for player in 0..n_players {
-            // if alive_players.len() < 2 {
-            if player_statuses
-                .iter()
-                .filter(|status| status.is_alive())
-                .count()
-                < 2
-            {
-                // condition to end the game
-                // need to check here, since game can end after any move
+        if settings.simultaneous {
+            if game.is_over() {
                 if verbose {
                     println!("done");
                 }
                 break 'mainloop;
             }
-
-            // if !alive_players.contains(&player) {
-            //     // skip dead players
-            //     continue;
-            // }
-
-            if !player_statuses[player].is_alive() {
-                // skip dead players
-                continue;
-            }
-
-            read_sender.send(Message::AskMove(player)).unwrap();
-            listener_sender.send(player).unwrap();
-            let timeout_time = if first_loop {
-                10 * time_limit
-            } else {
-                time_limit
-            };
-
-            // receive read messages from reading thread
-            let line = match readline_receiver.recv_timeout(Duration::from_millis(timeout_time)) {
-                Ok(line) => line,
-                Err(_) => {
+            handle_broken_pipes(
+                game,
+                channels,
+                processes,
+                &settings.sandbox,
+                player_statuses,
+                verbose,
+            )?;
+            let alive_players = (0..n_players)
+                .filter(|player| game.is_alive(*player))
+                .collect_vec();
+
+            let moves = request_moves(
+                &alive_players,
+                &mut clock,
+                channels,
+                game,
+                processes,
+                &settings.sandbox,
+                player_statuses,
+                verbose,
+            )?;
+            let outcomes = game.apply_simultaneous_moves(&moves);
+            channels.send(Message::CommunicateTurn(moves.clone()))?;
+            log_times(&clock, &alive_players, channels)?;
+            for ((player, _), outcome) in moves.into_iter().zip(outcomes) {
+                if outcome.is_loss() {
+                    if verbose {
+                        println!("Killing player {player} due to losing move");
+                    }
                     kill_player(
                         player,
-                        &read_sender,
-                        LossReason::TimeOut,
-                        &mut player_statuses,
-                    );
-                    let _ = children[player].kill(); // TODO: maybe remove if we have a good plan for when to kill processes
-                    println!("Timeout {}", player);
+                        channels,
+                        processes,
+                        LossReason::LosingMove,
+                        player_statuses,
+                    )?;
+                }
+            }
+            broadcast_items(game, channels)?;
+
+            if verbose {
+                println!("{}", game);
+            }
+        } else {
+            for player in 0..n_players {
+                if game.is_over() {
+                    // condition to end the game
+                    // need to check here, since game can end after any move
                     if verbose {
-                        println!("Killing player {player} due to timeout");
+                        println!("done");
                     }
+                    break 'mainloop;
+                }
+
+                handle_broken_pipes(
+                    game,
+                    channels,
+                    processes,
+                    &settings.sandbox,
+                    player_statuses,
+                    verbose,
+                )?;
+                if !game.is_alive(player) {
+                    // skip dead players
                     continue;
                 }
-            };
-            // .to_owned();
 
-            // trim_newline(&mut line);
-            if verbose {
-                println!("<-p{player}  \"{}\"", line.trim());
-            }
-            match line.trim().parse() {
-                Ok(direction) => {
-                    if !game.move_player(player, direction) {
+                let moves = request_moves(
+                    &[player],
+                    &mut clock,
+                    channels,
+                    game,
+                    processes,
+                    &settings.sandbox,
+                    player_statuses,
+                    verbose,
+                )?;
+                for (player, direction) in moves {
+                    if game.apply_move(player, direction).is_loss() {
                         if verbose {
                             println!("Killing player {player} due to losing move");
                         }
                         kill_player(
                             player,
-                            &read_sender,
+                            channels,
+                            processes,
                             LossReason::LosingMove,
-                            &mut player_statuses,
-                        );
+                            player_statuses,
+                        )?;
                     }
-                    read_sender
-                        .send(Message::CommunicateMove { direction, player })
-                        .unwrap();
+                    channels.send(Message::CommunicateMove { direction, player })?;
                 }
-                Err(_) => {
-                    // invalid move input from player
-                    if verbose {
-                        println!("Killing player {player} due to invalid input");
-                    }
-                    kill_player(
-                        player,
-                        &read_sender,
-                        LossReason::InvalidInput,
-                        &mut player_statuses,
-                    );
-                }
-            }
-
-            // TODO: should we kill a process when we kill the player?
-            // TODO: kill players when they do not accept input
-            // TODO: add time limit for players
+                log_times(&clock, &[player], channels)?;
+                broadcast_items(game, channels)?;
 
-            if verbose {
-                println!("{}", game);
+                if verbose {
+                    println!("{}", game);
+                }
             }
-            first_loop = false;
         }
+        game.end_turn();
+        turn.fetch_add(1, Ordering::Relaxed);
+        clock.end_turn();
     }
-    for i in 0..n_players {
-        if player_statuses[i].is_alive() {
-            read_sender.send(Message::Kill(i)).unwrap(); // kill winning player
-                                                         // TODO because kill_player takes a LossReason, we cannot use it to kill the winner.
-                                                         // change kill_player, or leave as an exception like this?
+    Ok(())
+}
+
+/// Takes players that stopped reading their stdin out of the game
+fn handle_broken_pipes<G: GameRules>(
+    game: &mut G,
+    channels: &PlayerChannels,
+    processes: &mut Processes,
+    sandbox: &Sandbox,
+    player_statuses: &mut [PlayerStatus],
+    verbose: bool,
+) -> Result<(), RunnerError> {
+    while let Ok(player) = channels.broken_pipes.try_recv() {
+        if !player_statuses[player].is_alive() {
+            continue;
+        }
+        let reason = lost_connection_reason(processes, sandbox, player, LossReason::BrokenPipe);
+        if verbose {
+            println!("Killing player {player}: {reason}");
         }
-        let _ = children[i].kill();
+        game.eliminate(player);
+        kill_player(player, channels, processes, reason, player_statuses)?;
     }
-    // (winner, player_results)
-    let player_results = player_statuses
-        .into_iter()
-        .map(PlayerResult::from)
+    Ok(())
+}
+
+/// Why we lost the connection to a player: `LimitExceeded` if its process was stopped by a limit
+/// of the sandbox, `Crashed` if it exited otherwise, `otherwise` if it is still running. Gives
+/// the process a moment to exit, since its pipes close just before it is done.
+fn lost_connection_reason(
+    processes: &mut Processes,
+    sandbox: &Sandbox,
+    player: usize,
+    otherwise: LossReason,
+) -> LossReason {
+    let Some(exit) = processes.wait_timeout(player, Duration::from_millis(50)) else {
+        return otherwise;
+    };
+    match sandbox.limit_exceeded(exit.status, exit.cpu_time) {
+        Some(limit) => LossReason::LimitExceeded { limit },
+        None => LossReason::Crashed {
+            exit_status: exit.status,
+        },
+    }
+}
+
+/// Logs the time the given players have left when playing with a time control, and how long
+/// their last move took when limiting CPU time
+fn log_times(
+    clock: &Clock,
+    players: &[usize],
+    channels: &PlayerChannels,
+) -> Result<(), RunnerError> {
+    let timings = players
+        .iter()
+        .filter_map(|&player| {
+            clock
+                .last_move(player)
+                .map(|(cpu, wall)| (player, cpu, wall))
+        })
         .collect_vec();
-    let Some(winner) = player_results
+    if !timings.is_empty() {
+        channels.send(Message::LogTiming(timings))?;
+    }
+    let times = players
         .iter()
-        .position(PlayerResult::is_winner) else {
-            return None;
+        .filter_map(|&player| clock.remaining(player).map(|time| (player, time)))
+        .collect_vec();
+    if !times.is_empty() {
+        channels.send(Message::LogClock(times))?;
+    }
+    Ok(())
+}
+
+/// Sends items spawned by the game (e.g. food) to all players and the log
+fn broadcast_items<G: GameRules>(
+    game: &mut G,
+    channels: &PlayerChannels,
+) -> Result<(), RunnerError> {
+    for item in game.spawn_items() {
+        channels.send(Message::Broadcast(item))?;
+    }
+    Ok(())
+}
+
+/// Sends `move` to all given players at once and waits for their answers until their time limits
+/// run out. Players that time out or answer with something that is not a direction are killed.
+/// The time every player used is charged to its clock. Returns the moves of the remaining
+/// players, in the order the players were given.
+#[allow(clippy::too_many_arguments)]
+fn request_moves<G: GameRules>(
+    players: &[usize],
+    clock: &mut Clock,
+    channels: &PlayerChannels,
+    game: &mut G,
+    processes: &mut Processes,
+    sandbox: &Sandbox,
+    player_statuses: &mut [PlayerStatus],
+    verbose: bool,
+) -> Result<Vec<(usize, Direction)>, RunnerError> {
+    let mut stopwatches = HashMap::new();
+    for &player in players {
+        // before the clock starts, so that it is not charged to the player
+        let state = channels.protocols[player]
+            .accepts(Feature::FullState)
+            .then(|| game.state());
+        processes.resume(player);
+        stopwatches.insert(player, clock.start(player, processes.id(player)));
+        channels.send(Message::AskMove {
+            player,
+            remaining_time: clock.remaining(player),
+            state,
+        })?;
+        let _ = channels.listeners[player].send(());
+    }
+
+    let mut answers = HashMap::new();
+    let mut timeouts = HashMap::new();
+    // wait as long as any of the players that have not answered yet still has time
+    loop {
+        let next_checks = stopwatches
+            .iter()
+            .filter(|(player, _)| !answers.contains_key(*player) && !timeouts.contains_key(*player))
+            .map(|(_, stopwatch)| stopwatch.next_check())
+            .collect_vec();
+        if next_checks.is_empty() {
+            break;
+        }
+        // players without a time limit are waited for as long as it takes
+        let received = match next_checks.into_iter().flatten().min() {
+            Some(next_check) => channels
+                .lines
+                .recv_timeout(next_check.saturating_duration_since(Instant::now())),
+            None => channels
+                .lines
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
         };
+        match received {
+            Ok((player, line)) => {
+                // lines from players that are not asked right now are late answers of players
+                // that already timed out, so we ignore them
+                if answers.contains_key(&player) || timeouts.contains_key(&player) {
+                    continue;
+                }
+                let Some(stopwatch) = stopwatches.get(&player) else {
+                    continue;
+                };
+                let time = stopwatch.read();
+                processes.pause(player);
+                if stopwatch.is_over(&time) {
+                    timeouts.insert(player, time);
+                } else {
+                    answers.insert(player, (line, time));
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                for (&player, stopwatch) in &stopwatches {
+                    if answers.contains_key(&player) || timeouts.contains_key(&player) {
+                        continue;
+                    }
+                    let time = stopwatch.read();
+                    if stopwatch.is_over(&time) {
+                        timeouts.insert(player, time);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
 
-    Some((winner, player_results))
+    // players that could not be asked did not time out, they stopped reading
+    handle_broken_pipes(game, channels, processes, sandbox, player_statuses, verbose)?;
+
+    let mut moves = Vec::new();
+    for &player in players {
+        if !player_statuses[player].is_alive() {
+            continue;
+        }
+        let Some((line, time)) = answers.remove(&player) else {
+            let time = timeouts
+                .remove(&player)
+                .unwrap_or_else(|| stopwatches[&player].read());
+            clock.flag(player, time);
+            game.eliminate(player);
+            kill_player(
+                player,
+                channels,
+                processes,
+                LossReason::TimeOut,
+                player_statuses,
+            )?;
+            // it may still be computing, while other players that go out are told to stop and
+            // get the grace period to exit when the game is over, see `Processes::shutdown`
+            processes.kill(player);
+            if verbose {
+                println!("Killing player {player} due to timeout");
+            }
+            continue;
+        };
+        clock.charge(player, time);
+
+        if line.is_empty() {
+            // end of stdout, most likely because the script stopped
+            let reason =
+                lost_connection_reason(processes, sandbox, player, LossReason::ClosedStdout);
+            if verbose {
+                println!("Killing player {player}: {reason}");
+            }
+            game.eliminate(player);
+            kill_player(player, channels, processes, reason, player_statuses)?;
+            continue;
+        }
+
+        if verbose {
+            println!("<-p{player}  \"{}\"", line.trim());
+        }
+        match channels.protocols[player].format().parse_answer(&line) {
+            Ok(direction) => moves.push((player, direction)),
+            Err(_) => {
+                // invalid move input from player
+                if verbose {
+                    println!("Killing player {player} due to invalid input");
+                }
+                game.eliminate(player);
+                kill_player(
+                    player,
+                    channels,
+                    processes,
+                    LossReason::InvalidInput,
+                    player_statuses,
+                )?;
+            }
+        }
+    }
+    Ok(moves)
 }
 
 fn writing_process(
     n_players: usize,
     read_receiver: mpsc::Receiver<Message>,
-    mut writer: Option<LineWriter<File>>,
-    mut stdins: Vec<ChildStdin>,
+    mut writer: Option<LogWriter>,
+    mut stdins: Vec<Option<Box<dyn Write + Send>>>,
+    protocols: Vec<Handshake>, // what every player agreed on in the handshake
     write_sender: mpsc::Sender<usize>,
     verbose: bool,
-) {
+) -> io::Result<()> {
     use Message as M;
 
-    let mut alive_players: HashSet<usize> = (0..n_players).collect();
+    let mut log_error = None;
+    let mut alive_players: HashSet<usize> = (0..n_players)
+        .filter(|player| stdins[*player].is_some())
+        .collect();
     for message in read_receiver.iter() {
         match message {
             M::CommunicateMove { direction, player } => {
-                log(&format!("{player}:{direction}"), &mut writer).unwrap();
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_entry(&LogEntry::Move { player, direction })
+                });
 
                 for (opponent_player, stdin) in stdins.iter_mut().enumerate() {
                     if opponent_player == player || !alive_players.contains(&opponent_player) {
@@ -385,7 +1415,9 @@ fn writing_process(
                     }
 
                     write_to_player(
-                        &format!("{}:{}", player, direction),
+                        &protocols[opponent_player]
+                            .format()
+                            .encode(&Instruction::Move { player, direction }),
                         opponent_player,
                         stdin,
                         &write_sender,
@@ -395,9 +1427,55 @@ fn writing_process(
                 }
             }
 
-            M::AskMove(player) => {
+            M::CommunicateTurn(moves) => {
+                // the whole turn goes on a single line, so it can be replayed as one
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_entry(&LogEntry::Turn(moves.clone()))
+                });
+
+                for (player, direction) in moves {
+                    for (opponent_player, stdin) in stdins.iter_mut().enumerate() {
+                        if opponent_player == player || !alive_players.contains(&opponent_player) {
+                            continue;
+                        }
+
+                        write_to_player(
+                            &protocols[opponent_player]
+                                .format()
+                                .encode(&Instruction::Move { player, direction }),
+                            opponent_player,
+                            stdin,
+                            &write_sender,
+                            &mut alive_players,
+                            verbose,
+                        );
+                    }
+                }
+            }
+
+            M::AskMove {
+                player,
+                remaining_time,
+                state,
+            } => {
+                if let Some(state) = state {
+                    write_to_player(
+                        &protocols[player]
+                            .format()
+                            .encode(&Instruction::State(state)),
+                        player,
+                        &mut stdins[player],
+                        &write_sender,
+                        &mut alive_players,
+                        verbose,
+                    );
+                }
+                let remaining_time =
+                    remaining_time.filter(|_| protocols[player].accepts(Feature::TimeBank));
                 write_to_player(
-                    "move",
+                    &protocols[player]
+                        .format()
+                        .encode(&Instruction::AskMove { remaining_time }),
                     player,
                     &mut stdins[player],
                     &write_sender,
@@ -406,10 +1484,22 @@ fn writing_process(
                 );
             }
 
-            M::Kill(player) => {
+            M::Kill { player, reason } => {
+                if let Some(reason) = reason {
+                    log(&mut writer, &mut log_error, |writer| {
+                        writer.write_entry(&LogEntry::Event(Instruction::Out {
+                            player,
+                            reason: Some(reason),
+                        }))
+                    });
+                }
                 alive_players.remove(&player);
+                // bots that speak version 1 do not expect a reason
+                let reason_for = |player: usize| reason.filter(|_| protocols[player].version >= 2);
                 write_to_player(
-                    "stop",
+                    &protocols[player].format().encode(&Instruction::Stop {
+                        reason: reason_for(player),
+                    }),
                     player,
                     &mut stdins[player],
                     &write_sender,
@@ -425,7 +1515,12 @@ fn writing_process(
                     }
 
                     write_to_player(
-                        &format!("out:{}", player),
+                        &protocols[opponent_player]
+                            .format()
+                            .encode(&Instruction::Out {
+                                player,
+                                reason: reason_for(opponent_player),
+                            }),
                         opponent_player,
                         stdin,
                         &write_sender,
@@ -435,10 +1530,16 @@ fn writing_process(
                 }
             }
 
-            M::SendHeader(header) => {
+            M::SendHeader {
+                header,
+                names,
+                simultaneous,
+            } => {
                 for (player, stdin) in stdins.iter_mut().enumerate() {
                     write_to_player(
-                        &format!("{header}\n{player}"),
+                        &protocols[player]
+                            .format()
+                            .header(&protocols[player].filter_header(&header), player),
                         player,
                         stdin,
                         &write_sender,
@@ -446,27 +1547,102 @@ fn writing_process(
                         verbose,
                     );
                 }
-                log(&header, &mut writer).unwrap();
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_header(&header, &names, simultaneous)
+                });
+            }
+
+            M::Broadcast(instruction) => {
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_entry(&LogEntry::Event(instruction.clone()))
+                });
+                for (player, stdin) in stdins.iter_mut().enumerate() {
+                    if !alive_players.contains(&player) {
+                        continue;
+                    }
+                    write_to_player(
+                        &protocols[player].format().encode(&instruction),
+                        player,
+                        stdin,
+                        &write_sender,
+                        &mut alive_players,
+                        verbose,
+                    );
+                }
+            }
+
+            M::LogClock(times) => {
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_entry(&LogEntry::Clock(times))
+                });
+            }
+
+            M::LogTiming(timings) => {
+                log(&mut writer, &mut log_error, |writer| {
+                    writer.write_entry(&LogEntry::Timing(timings))
+                });
             }
         }
     }
+    log_error.map_or(Ok(()), Err)
 }
 
-fn log_summary(writer: &mut LineWriter<File>, wins: Vec<i32>) -> Result<(), std::io::Error> {
-    let n_games: i32 = wins.iter().sum();
-    writer.write_fmt(format_args!("n_games:{n_games}\n"))?;
-    for (pn, p_wins) in wins.iter().enumerate() {
-        writer.write_fmt(format_args!("{pn}:{p_wins}\n"))?
+/// Writes to the log, if there is one. The game goes on if writing fails, but the log is abandoned
+/// and the error is kept to be reported when the game is over.
+fn log(
+    writer: &mut Option<LogWriter>,
+    log_error: &mut Option<io::Error>,
+    write: impl FnOnce(&mut LogWriter) -> io::Result<()>,
+) {
+    if let Some(log_writer) = writer.as_mut() {
+        if let Err(err) = write(log_writer) {
+            *writer = None;
+            *log_error = Some(err);
+        }
     }
-    let winner = wins
-        .iter()
-        .enumerate()
-        .max_by_key(|(_, &value)| value)
-        .map(|(idx, _)| idx)
-        .unwrap(); // argmax
+}
 
+fn log_summary(
+    writer: &mut LineWriter<File>,
+    bots: &[BotSpec],
+    match_stats: &MatchStats,
+    winner: usize,
+) -> Result<(), std::io::Error> {
+    writer.write_fmt(format_args!("n_games:{}\n", match_stats.games))?;
+    for (pn, bot) in bots.iter().enumerate() {
+        writer.write_fmt(format_args!("name:{pn}:{}\n", bot.name))?
+    }
+    for (pn, p_wins) in match_stats.wins.iter().enumerate() {
+        writer.write_fmt(format_args!("{pn}:{p_wins}\n"))?
+    }
+    writer.write_fmt(format_args!("draws:{}\n", match_stats.draws))?;
     writer.write_fmt(format_args!("winner:{winner}\n"))?;
 
+    // how often every player lost for each reason, in order of player
+    for (reason, counts) in [
+        ("losing_moves", &match_stats.losing_moves),
+        ("timeouts", &match_stats.timeouts),
+        ("invalid_inputs", &match_stats.invalid_inputs),
+        ("turn_limits", &match_stats.turn_limits),
+        ("crashes", &match_stats.crashes),
+        ("closed_stdouts", &match_stats.closed_stdouts),
+        ("broken_pipes", &match_stats.broken_pipes),
+        ("spawn_failures", &match_stats.spawn_failures),
+        ("limits_exceeded", &match_stats.limits_exceeded),
+    ] {
+        writer.write_fmt(format_args!("{reason}:{}\n", counts.iter().join(",")))?;
+    }
+
+    if !match_stats.failed_games.is_empty() {
+        writer.write_fmt(format_args!(
+            "failed_games:{}\n",
+            match_stats.failed_games.len()
+        ))?;
+        for (gameno, reason) in &match_stats.failed_games {
+            writer.write_fmt(format_args!("game{gameno}:{reason}\n"))?;
+        }
+    }
+
     Ok(())
 }
 
@@ -475,7 +1651,16 @@ struct MatchStats {
     timeouts: Vec<i32>,
     invalid_inputs: Vec<i32>,
     losing_moves: Vec<i32>,
+    turn_limits: Vec<i32>,
+    crashes: Vec<i32>,
+    closed_stdouts: Vec<i32>,
+    broken_pipes: Vec<i32>,
+    spawn_failures: Vec<i32>,
+    limits_exceeded: Vec<i32>,
     wins: Vec<i32>,
+    games: usize, // every game that was started, including tiebreakers and failed games
+    draws: usize, // games that were played to the end without a winner
+    failed_games: Vec<(usize, RunnerError)>, // game number and what went wrong
 }
 
 impl MatchStats {
@@ -485,23 +1670,50 @@ impl MatchStats {
             timeouts: vec![0; n_players],
             invalid_inputs: vec![0; n_players],
             losing_moves: vec![0; n_players],
+            turn_limits: vec![0; n_players],
+            crashes: vec![0; n_players],
+            closed_stdouts: vec![0; n_players],
+            broken_pipes: vec![0; n_players],
+            spawn_failures: vec![0; n_players],
+            limits_exceeded: vec![0; n_players],
             wins: vec![0; n_players],
+            games: 0,
+            draws: 0,
+            failed_games: Vec::new(),
         }
     }
 
-    fn update(&mut self, player_results: Vec<&PlayerResult>) {
+    /// Adds the results of a game. `players` are the ids of the players in the match, in the
+    /// order in which they played in the game
+    fn update(&mut self, players: &[usize], player_results: &[PlayerResult]) {
         use LossReason as LR;
         use PlayerResult as PR;
-        for (player, result) in player_results.iter().enumerate() {
+        self.games += 1;
+        if !player_results.contains(&PR::Winner) {
+            self.draws += 1;
+        }
+        for (&player, result) in players.iter().zip(player_results) {
             match result {
                 PR::Loser(LR::InvalidInput) => self.invalid_inputs[player] += 1,
                 PR::Loser(LR::LosingMove) => self.losing_moves[player] += 1,
                 PR::Loser(LR::TimeOut) => self.timeouts[player] += 1,
+                PR::Loser(LR::TurnLimit) => self.turn_limits[player] += 1,
+                PR::Loser(LR::Crashed { .. }) => self.crashes[player] += 1,
+                PR::Loser(LR::ClosedStdout) => self.closed_stdouts[player] += 1,
+                PR::Loser(LR::BrokenPipe) => self.broken_pipes[player] += 1,
+                PR::Loser(LR::SpawnFailed { .. }) => self.spawn_failures[player] += 1,
+                PR::Loser(LR::LimitExceeded { .. }) => self.limits_exceeded[player] += 1,
                 PR::Winner => self.wins[player] += 1,
             }
         }
     }
 
+    /// Records a game that could not be played to the end
+    fn fail(&mut self, gameno: usize, err: RunnerError) {
+        self.games += 1;
+        self.failed_games.push((gameno, err));
+    }
+
     fn most_wins(&self) -> Vec<usize> {
         let Some(max_wins) = self.wins.iter().max() else {
             return Vec::new();
@@ -512,135 +1724,267 @@ impl MatchStats {
     }
 }
 
-pub fn play_match(
-    scripts: Vec<&str>,
-    width: usize,
-    height: usize,
+/// Plays `n_games` games between the scripts, with random starting positions and move order, and
+/// returns the player that won most games. Plays a tiebreaker if necessary. A game that fails is
+/// recorded in the summary and the match goes on, unless every game fails.
+pub fn play_match<G: GameRules>(
+    bots: &[BotSpec],
+    settings: &GameSettings,
     n_games: usize,
-    time_limit: u64,
     summary_filename: &Path,
-    gamelogs_folder: Option<PathBuf>,
-) -> usize {
-    if let Some(folder_name) = &gamelogs_folder {
+    gamelogs_folder: Option<&Path>,
+) -> Result<usize, RunnerError> {
+    let n_players = bots.len();
+    settings.validate(n_players, None)?;
+
+    if let Some(folder_name) = gamelogs_folder {
         // create folder for logs if needed
-        std::fs::create_dir_all(folder_name).unwrap();
+        std::fs::create_dir_all(folder_name).map_err(|source| RunnerError::Io {
+            path: folder_name.to_path_buf(),
+            source,
+        })?;
+    }
+    let mut gamelog_path = gamelogs_folder.map(Path::to_path_buf); // we will be adding filename
+    if let Some(path) = gamelog_path.as_mut() {
+        path.push("log.txt"); // dummy file name, will be replaced
     }
-    let mut gamelog_path = gamelogs_folder; // rename because we will be adding filename
-    gamelog_path.as_mut().map(|path| path.push("log.txt")); // dummy file name, will be replaced
-    let n_players = scripts.len();
 
-    let mut logwriter = LineWriter::new(File::create(summary_filename).unwrap());
+    let summary_error = |source| RunnerError::Io {
+        path: summary_filename.to_path_buf(),
+        source,
+    };
+    let mut logwriter = LineWriter::new(File::create(summary_filename).map_err(summary_error)?);
 
-    // keeping track of stats
-    // let mut wins = vec![0; n_players];
-    // let mut timeouts = vec![0; n_players];
-    // let mut invalid_inputs = vec![0; n_players];
-    // let mut losing_moves = vec![0; n_players];
     let mut match_stats = MatchStats::new(n_players);
-    let mut tagged_scripts: Vec<(usize, &str)> = scripts.into_iter().enumerate().collect();
+    let mut tagged_bots: Vec<(usize, &BotSpec)> = bots.iter().enumerate().collect();
 
-    let mut shuffled_players: Vec<usize>;
-    let mut shuffled_scripts: Vec<&str>;
+    // Plays a game with the players in `tagged_bots` in random order. Returns the winner, if any
+    let mut play_shuffled =
+        |tagged_bots: &mut Vec<(usize, &BotSpec)>, gameno: usize, match_stats: &mut MatchStats| {
+            tagged_bots.shuffle(&mut thread_rng()); // shuffle player ids and bots together so we can unshuffle the results from the game (i.e. the player corresponding to the i'th index after shuffling has id shuffled_players[i])
+            let (shuffled_players, shuffled_bots): (Vec<usize>, Vec<BotSpec>) = tagged_bots
+                .iter()
+                .map(|&(player, bot)| (player, bot.clone()))
+                .unzip();
+
+            if let Some(path) = gamelog_path.as_mut() {
+                path.set_file_name(format!("log{gameno}.txt"));
+            }
+
+            match play_game::<G>(
+                &shuffled_bots,
+                None,
+                settings,
+                gamelog_path.as_deref(),
+                false,
+            ) {
+                Ok(result) => {
+                    match_stats.update(&shuffled_players, &result.player_results);
+                    result.winner.map(|winner| shuffled_players[winner])
+                }
+                Err(err) => {
+                    match_stats.fail(gameno, err);
+                    None
+                }
+            }
+        };
 
     for gameno in 0..n_games {
-        tagged_scripts.shuffle(&mut thread_rng()); // shuffle player ids and scripts together so we can unshuffle the results from the game (i.e. the player corresponding to the i'th index after shuffling has id shuffled_players[i])
-        (shuffled_players, shuffled_scripts) = tagged_scripts
-            .iter()
-            .map(|(n, script)| (n, *script))
-            .unzip();
-
-        gamelog_path
-            .as_mut()
-            .map(|path| path.set_file_name(format!("log{gameno}.txt")));
-
-        if let Some((winner, player_results)) = play_game(
-            &shuffled_scripts,
-            None,
-            width,
-            height,
-            gamelog_path.as_deref(),
-            false,
-            time_limit,
-        ) {
-            // wins[shuffled_players[winner]] += 1;
-            let unshuffled_player_results: Vec<&PlayerResult> = (0..n_players)
-                .map(|idx| &player_results[shuffled_players[idx]])
-                .collect();
-
-            match_stats.update(unshuffled_player_results);
-            // println!("Game done {}", shuffled_players[winner])
-        } else {
-            // println!("Game failed, no winner")
+        play_shuffled(&mut tagged_bots, gameno, &mut match_stats);
+    }
+    if n_games > 0 && match_stats.failed_games.len() == n_games {
+        // every game failed, so there is nothing to summarize. Most likely they all failed for
+        // the same reason
+        if let Some((_, err)) = match_stats.failed_games.pop() {
+            return Err(err);
         }
     }
 
     // Tie breaker: if two or more players share the highest amount of wins, we play another game, the winner of which is the winner of the match
-    let max_wins = *match_stats.wins.iter().max().unwrap();
-    // TODO: Error handling: make sure scripts.len != 0
-
-    tagged_scripts = tagged_scripts
-        .into_iter()
-        .filter(|(pn, _)| match_stats.wins[*pn] == max_wins)
-        .collect(); // filter only tied winning players
-
-    if tagged_scripts.len() == 0 {
-        panic!("") // TODO: handle error. Can only occur if scripts.len() == 0
-    }
-    if let [(pn, _)] = tagged_scripts[..] {
-        log_summary(&mut logwriter, match_stats.wins).unwrap();
-        return pn;
-    } // length 1 => return
-
-    // 2 or more tied players: tiebreaker
-    let mut gameno = n_games - 1;
-    for _ in 0..10 {
-        // in case game fails, we try a few times
-        // should only occur once,
-        // but if play_game fails, might have to redo
-        // 10 should be a safe margin
-        tagged_scripts.shuffle(&mut thread_rng()); // shuffle player ids and scripts together so we can unshuffle the results from the game (i.e. the player corresponding to the i'th index after shuffling has id shuffled_players[i])
-        (shuffled_players, shuffled_scripts) = tagged_scripts
-            .iter()
-            .map(|(n, script)| (n, *script))
-            .unzip();
-
-        gamelog_path
-            .as_mut()
-            .map(|path| path.set_file_name(format!("log{gameno}.txt")));
-
-        if let Some((winner, player_results)) = play_game(
-            &shuffled_scripts,
-            None,
-            width,
-            height,
-            gamelog_path.as_deref(),
-            false,
-            time_limit,
-        ) {
-            // wins[shuffled_players[winner]] += 1;
-            let unshuffled_player_results: Vec<&PlayerResult> = (0..n_players)
-                .map(|idx| &player_results[shuffled_players[idx]])
-                .collect();
-
-            match_stats.update(unshuffled_player_results);
-            println!("Game done {}", shuffled_players[winner]);
-            return match_stats.most_wins()[0];
-        } else {
-            println!("Game failed, no winner")
+    let tied_players = match_stats.most_wins();
+    tagged_bots.retain(|(pn, _)| tied_players.contains(pn)); // filter only tied winning players
+
+    let mut winner = tagged_bots[0].0; // failsafe
+    if tagged_bots.len() > 1 {
+        // 2 or more tied players: tiebreaker
+        for gameno in n_games..n_games + 10 {
+            // in case game fails, we try a few times
+            // should only occur once,
+            // but if play_game fails, might have to redo
+            // 10 should be a safe margin
+            if let Some(tiebreaker_winner) =
+                play_shuffled(&mut tagged_bots, gameno, &mut match_stats)
+            {
+                winner = tiebreaker_winner;
+                break;
+            }
         }
-        gameno += 1;
     }
 
-    tagged_scripts[0].0 // failsafe
+    log_summary(&mut logwriter, bots, &match_stats, winner).map_err(summary_error)?;
+    Ok(winner)
 }
 
 fn kill_player(
     player: usize,
-    sender: &mpsc::Sender<Message>,
+    channels: &PlayerChannels,
+    processes: &mut Processes,
     reason: LossReason,
-    player_statuses: &mut Vec<PlayerStatus>,
-) {
+    player_statuses: &mut [PlayerStatus],
+) -> Result<(), RunnerError> {
     // alive_players.remove(&player);
+    let out_reason = reason.out_reason();
     player_statuses[player] = PlayerStatus::Dead(reason);
-    sender.send(Message::Kill(player)).unwrap();
+    processes.resume(player); // so that it can read that it has to stop
+    channels.send(Message::Kill {
+        player,
+        reason: Some(out_reason),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> GameSettings {
+        Runner::new(["a.py", "b.py"])
+            .size(4, 3)
+            .map("..#.\n....\n....".parse().unwrap())
+            .settings()
+    }
+
+    #[test]
+    fn validate() {
+        let settings = settings();
+        assert_eq!(settings.validate(2, Some(&[(0, 0), (3, 2)])), Ok(()));
+        assert_eq!(settings.validate(0, None), Err(ConfigError::NoScripts));
+        assert_eq!(
+            settings.validate(2, Some(&[(0, 0)])),
+            Err(ConfigError::WrongNumberOfPositions {
+                given: 1,
+                needed: 2
+            })
+        );
+        assert_eq!(
+            settings.validate(2, Some(&[(0, 0), (4, 0)])),
+            Err(ConfigError::PositionOutsideBoard { x: 4, y: 0 })
+        );
+        assert_eq!(
+            settings.validate(2, Some(&[(2, 0), (0, 0)])),
+            Err(ConfigError::PositionBlocked { x: 2, y: 0 })
+        );
+        assert_eq!(
+            settings.validate(2, Some(&[(1, 1), (1, 1)])),
+            Err(ConfigError::PositionBlocked { x: 1, y: 1 })
+        );
+        assert_eq!(
+            settings.validate(12, None),
+            Err(ConfigError::BoardTooSmall {
+                free_cells: 11,
+                needed: 12
+            })
+        );
+    }
+
+    #[test]
+    fn handshakes() {
+        let mut connection = Connection::thread(|mut input, mut output| {
+            let mut greeting = String::new();
+            input.read_line(&mut greeting).unwrap();
+            assert!(greeting.starts_with("snakerunner:2:"));
+            writeln!(output, "hello:2:map:tester").unwrap();
+        })
+        .unwrap();
+        assert_eq!(
+            handshake(&mut connection),
+            Some(Handshake {
+                version: 2,
+                features: vec![Feature::Map],
+                name: Some("tester".to_string()),
+            })
+        );
+        // a bot from before the handshake does not understand the greeting
+        let mut connection = Connection::thread(|_, mut output| {
+            let _ = writeln!(output, "N");
+        })
+        .unwrap();
+        assert_eq!(handshake(&mut connection), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handshake_only_for_version_2() {
+        // echoes the first line it gets
+        let mut bot = BotSpec {
+            name: "echo".to_string(),
+            source: BotSource::Command {
+                args: vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    "read line; echo \"$line\"".to_string(),
+                ],
+                cwd: None,
+            },
+            protocol: None,
+        };
+        // a bot from before the handshake is not greeted, but gets the header first
+        let (mut connection, handshake) = connect_with_handshake(&bot, &settings()).unwrap();
+        assert_eq!(handshake, Handshake::legacy());
+        writeln!(connection.input, "10,10").unwrap();
+        connection.input.flush().unwrap();
+        let mut line = String::new();
+        connection.output.read_line(&mut line).unwrap();
+        assert_eq!(line, "10,10\n");
+        close(connection);
+        // a bot that speaks version 2 cannot play without answering the greeting
+        bot.protocol = Some(PROTOCOL_VERSION);
+        assert!(connect_with_handshake(&bot, &settings()).is_err());
+    }
+
+    #[test]
+    fn stderr_next_to_log() {
+        assert_eq!(
+            stderr_path(Path::new("logs/log3.txt"), 1),
+            PathBuf::from("logs/log3.txt.p1.stderr")
+        );
+    }
+
+    #[test]
+    fn match_stats_unshuffle() {
+        let mut match_stats = MatchStats::new(3);
+        // player 2 played first and won, player 0 played second, player 1 third
+        match_stats.update(
+            &[2, 0, 1],
+            &[
+                PlayerResult::Winner,
+                PlayerResult::Loser(LossReason::TimeOut),
+                PlayerResult::Loser(LossReason::LosingMove),
+            ],
+        );
+        assert_eq!(match_stats.wins, vec![0, 0, 1]);
+        assert_eq!(match_stats.timeouts, vec![1, 0, 0]);
+        // tiebreakers are played by fewer players
+        match_stats.update(
+            &[1, 2],
+            &[
+                PlayerResult::Winner,
+                PlayerResult::Loser(LossReason::InvalidInput),
+            ],
+        );
+        assert_eq!(match_stats.wins, vec![0, 1, 1]);
+        assert_eq!(match_stats.invalid_inputs, vec![0, 0, 1]);
+        // games without a winner still count for the loss reasons
+        match_stats.update(
+            &[0, 1],
+            &[
+                PlayerResult::Loser(LossReason::ClosedStdout),
+                PlayerResult::Loser(LossReason::BrokenPipe),
+            ],
+        );
+        assert_eq!(match_stats.closed_stdouts, vec![1, 0, 0]);
+        assert_eq!(match_stats.broken_pipes, vec![0, 1, 0]);
+        assert_eq!(match_stats.most_wins(), vec![1, 2]);
+        match_stats.fail(3, RunnerError::WriterStopped);
+        assert_eq!((match_stats.games, match_stats.draws), (4, 1));
+    }
 }
@@ -0,0 +1,333 @@
+use std::io;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+/// Limits and isolation for the processes of the scripts. Limits are set with `setrlimit` just
+/// before a script is started, isolation uses Linux namespaces. Only supported on Linux.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sandbox {
+    /// Maximum size of the address space of a script in bytes. Includes the python interpreter
+    /// for python scripts
+    pub memory: Option<u64>,
+    /// CPU time in seconds a script may use during the whole game
+    pub cpu_seconds: Option<u64>,
+    /// Maximum number of processes. Note that Linux counts all processes of the user, not only
+    /// those started by the script
+    pub processes: Option<u64>,
+    /// Maximum number of open files, including stdin, stdout and stderr
+    pub open_files: Option<u64>,
+    /// Run every script in its own network namespace, without network access
+    pub isolate_network: bool,
+    /// Make the working directory of every script read-only, using a mount namespace
+    pub read_only_dir: bool,
+}
+
+/// A resource of which a script can use a limited amount
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceLimit {
+    Memory,
+    CpuTime,
+    Processes,
+    OpenFiles,
+}
+
+impl std::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Memory => "memory",
+                Self::CpuTime => "CPU time",
+                Self::Processes => "process",
+                Self::OpenFiles => "open file",
+            }
+        )
+    }
+}
+
+impl Sandbox {
+    /// Whether scripts run without any limits or isolation
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Which limit made a process exit, as far as the runner can tell from how it exited. The CPU
+    /// time limit is enforced with SIGXCPU and, a second later, SIGKILL, which is only put down to
+    /// the limit if the process used up its CPU time, since it may as well come from the OOM
+    /// killer or from outside the runner. Exceeding the other limits makes allocations, forks or
+    /// opening files fail inside the script, and what it does then cannot be told apart from any
+    /// other crash.
+    pub(crate) fn limit_exceeded(
+        &self,
+        exit_status: ExitStatus,
+        cpu_time: Option<Duration>,
+    ) -> Option<ResourceLimit> {
+        #[cfg(target_os = "linux")]
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            use std::os::unix::process::ExitStatusExt;
+            let used_up = cpu_time.is_some_and(|cpu_time| cpu_time.as_secs() >= cpu_seconds);
+            match exit_status.signal() {
+                Some(libc::SIGXCPU) => return Some(ResourceLimit::CpuTime),
+                Some(libc::SIGKILL) if used_up => return Some(ResourceLimit::CpuTime),
+                _ => {}
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (exit_status, cpu_time);
+        None
+    }
+
+    /// Makes the command apply the limits and isolation to the process it starts
+    #[cfg(target_os = "linux")]
+    pub(crate) fn apply(&self, command: &mut Command) -> io::Result<()> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let limits = [
+            (libc::RLIMIT_AS, self.memory.map(|memory| (memory, memory))),
+            // a second of grace between SIGXCPU and SIGKILL
+            (
+                libc::RLIMIT_CPU,
+                self.cpu_seconds.map(|seconds| (seconds, seconds + 1)),
+            ),
+            (libc::RLIMIT_NPROC, self.processes.map(|n| (n, n))),
+            (libc::RLIMIT_NOFILE, self.open_files.map(|n| (n, n))),
+        ]
+        .into_iter()
+        .filter_map(|(resource, limit)| limit.map(|(soft, hard)| (resource, soft, hard)))
+        .collect::<Vec<_>>();
+
+        let mut namespaces = 0;
+        if self.isolate_network {
+            // a user namespace lets unprivileged users create the other namespaces
+            namespaces |= libc::CLONE_NEWUSER | libc::CLONE_NEWNET;
+        }
+
+        // everything the new process needs is prepared here, since only async-signal-safe
+        // functions may be called between fork and exec
+        let read_only_dir = if self.read_only_dir {
+            namespaces |= libc::CLONE_NEWUSER | libc::CLONE_NEWNS;
+            let dir = match command.get_current_dir() {
+                Some(dir) => dir.canonicalize()?,
+                None => std::env::current_dir()?,
+            };
+            let dir = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            let flags = locked_mount_flags(&dir)?;
+            Some((dir, flags))
+        } else {
+            None
+        };
+
+        // SAFETY: the closure only makes system calls, it does not allocate or take locks
+        unsafe {
+            command.pre_exec(move || {
+                if namespaces != 0 && libc::unshare(namespaces) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some((dir, flags)) = &read_only_dir {
+                    let root = c"/".as_ptr();
+                    let null = std::ptr::null();
+                    // keep the read-only mount inside the namespace of the script
+                    check(libc::mount(
+                        null,
+                        root,
+                        null,
+                        libc::MS_REC | libc::MS_PRIVATE,
+                        std::ptr::null(),
+                    ))?;
+                    check(libc::mount(
+                        dir.as_ptr(),
+                        dir.as_ptr(),
+                        null,
+                        libc::MS_BIND | libc::MS_REC,
+                        std::ptr::null(),
+                    ))?;
+                    check(libc::mount(
+                        null,
+                        dir.as_ptr(),
+                        null,
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | flags,
+                        std::ptr::null(),
+                    ))?;
+                    // the process is still in the directory below the new mount
+                    check(libc::chdir(dir.as_ptr()))?;
+                }
+                for &(resource, soft, hard) in &limits {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft,
+                        rlim_max: hard,
+                    };
+                    check(libc::setrlimit(resource, &limit))?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn apply(&self, _command: &mut Command) -> io::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sandboxing is only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check(result: libc::c_int) -> io::Result<()> {
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Flags of the mount containing `dir` that have to be kept when remounting it from a user
+/// namespace, otherwise the kernel refuses
+#[cfg(target_os = "linux")]
+fn locked_mount_flags(dir: &std::ffi::CStr) -> io::Result<libc::c_ulong> {
+    // SAFETY: statvfs only writes to the struct we pass it
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    check(unsafe { libc::statvfs(dir.as_ptr(), &mut stat) })?;
+    Ok([
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .into_iter()
+    .filter(|(st_flag, _)| stat.f_flag & st_flag != 0)
+    .fold(0, |flags, (_, ms_flag)| flags | ms_flag))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+
+    fn output(sandbox: &Sandbox, script: &str) -> String {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        sandbox.apply(&mut command).unwrap();
+        String::from_utf8(command.output().unwrap().stdout).unwrap()
+    }
+
+    #[test]
+    fn rlimits() {
+        let sandbox = Sandbox {
+            open_files: Some(17),
+            cpu_seconds: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(output(&sandbox, "ulimit -n; ulimit -t"), "17\n3\n");
+    }
+
+    #[test]
+    fn cpu_limit_exceeded() {
+        let sandbox = Sandbox {
+            cpu_seconds: Some(1),
+            ..Default::default()
+        };
+        let killed = ExitStatus::from_raw(libc::SIGXCPU);
+        assert_eq!(
+            sandbox.limit_exceeded(killed, None),
+            Some(ResourceLimit::CpuTime)
+        );
+        assert_eq!(
+            sandbox.limit_exceeded(ExitStatus::from_raw(256), None),
+            None
+        );
+        assert_eq!(Sandbox::default().limit_exceeded(killed, None), None);
+        // SIGKILL at the hard limit, or from the OOM killer before the CPU time was used up
+        let killed = ExitStatus::from_raw(libc::SIGKILL);
+        assert_eq!(
+            sandbox.limit_exceeded(killed, Some(Duration::from_millis(2010))),
+            Some(ResourceLimit::CpuTime)
+        );
+        assert_eq!(
+            sandbox.limit_exceeded(killed, Some(Duration::from_millis(200))),
+            None
+        );
+    }
+
+    /// Whether scripts can be started in namespaces of their own, which not every system allows
+    /// unprivileged users
+    fn namespaces_available() -> bool {
+        let sandbox = Sandbox {
+            isolate_network: true,
+            ..Default::default()
+        };
+        let mut command = Command::new("true");
+        sandbox.apply(&mut command).unwrap();
+        let available = command.status().is_ok();
+        if !available {
+            eprintln!("skipped, user namespaces are not available");
+        }
+        available
+    }
+
+    #[test]
+    fn network_isolated() {
+        if !namespaces_available() {
+            return;
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connects = |sandbox: &Sandbox| {
+            let mut command = Command::new("bash");
+            command
+                .arg("-c")
+                .arg(format!("exec 3<>/dev/tcp/127.0.0.1/{port}"))
+                .stderr(Stdio::null());
+            sandbox.apply(&mut command).unwrap();
+            command.status().unwrap().success()
+        };
+        assert!(connects(&Sandbox::default()));
+        let sandbox = Sandbox {
+            isolate_network: true,
+            ..Default::default()
+        };
+        assert!(!connects(&sandbox));
+    }
+
+    #[test]
+    fn read_only_dir() {
+        if !namespaces_available() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("snakerunner-ro-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bot.txt"), "N").unwrap();
+        let sandbox = Sandbox {
+            read_only_dir: true,
+            ..Default::default()
+        };
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("cat bot.txt; touch written")
+            .current_dir(&dir)
+            .stderr(Stdio::null());
+        sandbox.apply(&mut command).unwrap();
+        let output = command.output().unwrap();
+        let written = dir.join("written").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // the script can read its directory, but not write to it
+        assert_eq!(output.stdout, b"N");
+        assert!(!output.status.success());
+        assert!(!written);
+    }
+}
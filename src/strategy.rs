@@ -0,0 +1,196 @@
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+use crate::game::Direction;
+use crate::gamelog::GameLog;
+use crate::parse_instruction::Instruction;
+use crate::protocol;
+use crate::rules::GameSetup;
+
+/// A bot written in Rust that plays inside the runner, without starting a process. The runner
+/// tells it about the game through these methods instead of through the IO interface. A new
+/// instance is created for every game, see `BotSpec::in_process`.
+pub trait Bot {
+    /// Called once before the first move, with the setup of the game and the number of the
+    /// player the bot plays for
+    fn setup(&mut self, setup: &GameSetup, player: usize);
+
+    /// Another player made a move. Moves of the bot itself are not passed on, it has to keep
+    /// track of them in `choose_move`.
+    fn observe_move(&mut self, player: usize, direction: Direction);
+
+    /// Another player is out of the game
+    fn observe_out(&mut self, player: usize);
+
+    /// Food spawned, when playing with the classic rules
+    fn observe_food(&mut self, _position: (usize, usize)) {}
+
+    /// Chooses the next move. With a time control, `remaining_time` is the time in milliseconds
+    /// the player has left.
+    fn choose_move(&mut self, remaining_time: Option<u64>) -> Direction;
+}
+
+/// Creates a new instance of an in-process bot for every game it plays
+#[derive(Clone)]
+pub struct BotFactory(Arc<dyn Fn() -> Box<dyn Bot> + Send + Sync>);
+
+impl BotFactory {
+    pub fn new<S: Bot + 'static>(new: impl Fn() -> S + Send + Sync + 'static) -> Self {
+        Self(Arc::new(move || Box::new(new())))
+    }
+
+    pub(crate) fn create(&self) -> Box<dyn Bot> {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for BotFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BotFactory").finish_non_exhaustive()
+    }
+}
+
+/// Factories are only equal to their clones, so that a bot compares equal to its clones
+impl PartialEq for BotFactory {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Plays for a strategy: reads the input of a bot from `input`, passes it on to the strategy and
+/// writes the moves it chooses to `output`
+pub(crate) fn play(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    strategy: &mut dyn Bot,
+) -> io::Result<()> {
+    let Some((setup, player)) = read_header(&mut input, &mut output)? else {
+        return Ok(());
+    };
+    strategy.setup(&setup, player);
+    while let Some(line) = read_line(&mut input)? {
+        let Ok(instruction) = line.parse() else {
+            continue;
+        };
+        match instruction {
+            Instruction::AskMove { remaining_time } => {
+                writeln!(output, "{}", strategy.choose_move(remaining_time))?;
+                output.flush()?;
+            }
+            Instruction::Move { player, direction } => strategy.observe_move(player, direction),
+            Instruction::Out { player, .. } => strategy.observe_out(player),
+            Instruction::Food { position } => strategy.observe_food(position),
+            // not accepted in the handshake
+            Instruction::State(_) => {}
+            Instruction::Stop { .. } => break,
+        }
+    }
+    Ok(())
+}
+
+/// Reads the header the runner sends to a bot: the setup of the game and the number of the
+/// player. Answers the handshake before it on `output`, if the runner starts with one. `None` if
+/// the input ends before the header does.
+pub(crate) fn read_header(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<Option<(GameSetup, usize)>> {
+    // the header ends with the number of the player, the first plain number after the starting
+    // positions
+    let mut header = Vec::new();
+    let player = loop {
+        let Some(line) = read_line(input)? else {
+            return Ok(None);
+        };
+        if header.is_empty() {
+            // in-process bots only speak the line protocol
+            if let Some(reply) = protocol::reply_to(&line, &protocol::V1_FEATURES) {
+                writeln!(output, "{reply}")?;
+                output.flush()?;
+                continue;
+            }
+        }
+        let n_players = header
+            .get(1)
+            .and_then(|line: &String| line.parse::<usize>().ok());
+        if n_players.is_some_and(|n_players| header.len() >= n_players + 2) {
+            if let Ok(player) = line.parse() {
+                break player;
+            }
+        }
+        header.push(line);
+    };
+    let log: GameLog = header
+        .join("\n")
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Some((log.setup, player)))
+}
+
+/// Next line of `input` without the line ending, `None` at the end of the input
+pub(crate) fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Topology;
+
+    /// Remembers what it was told, and always moves east
+    #[derive(Default)]
+    struct Recorder {
+        setup: Option<(GameSetup, usize)>,
+        observed: Vec<String>,
+    }
+
+    impl Bot for Recorder {
+        fn setup(&mut self, setup: &GameSetup, player: usize) {
+            self.setup = Some((setup.clone(), player));
+        }
+
+        fn observe_move(&mut self, player: usize, direction: Direction) {
+            self.observed.push(format!("{player}:{direction}"));
+        }
+
+        fn observe_out(&mut self, player: usize) {
+            self.observed.push(format!("out:{player}"));
+        }
+
+        fn choose_move(&mut self, remaining_time: Option<u64>) -> Direction {
+            self.observed.push(format!("move {remaining_time:?}"));
+            Direction::East
+        }
+    }
+
+    #[test]
+    fn plays_strategy() {
+        let input = "snakerunner:2:time_bank,topology,json\n10,10\n2\n1,1\n5,5\ntopology:box\n1\n0:N\nmove 500\nout:0\nstop\nmove\n";
+        let mut output = Vec::new();
+        let mut recorder = Recorder::default();
+        play(input.as_bytes(), &mut output, &mut recorder).unwrap();
+        assert_eq!(output, b"hello:2:time_bank,topology:\nE\n");
+
+        let (setup, player) = recorder.setup.unwrap();
+        assert_eq!(player, 1);
+        assert_eq!(setup.starting_positions, [(1, 1), (5, 5)]);
+        assert_eq!(setup.topology, Topology::Box);
+        // nothing is passed on after stop
+        assert_eq!(recorder.observed, ["0:N", "move Some(500)", "out:0"]);
+    }
+
+    #[test]
+    fn invalid_header() {
+        let input = "10,10\n2\n1,1\n20,20\n0\n";
+        let err = read_header(&mut input.as_bytes(), &mut io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let input = "10,10\n2\n";
+        assert!(read_header(&mut input.as_bytes(), &mut io::sink())
+            .unwrap()
+            .is_none());
+    }
+}
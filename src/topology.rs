@@ -0,0 +1,124 @@
+use crate::game::Direction;
+
+/// The surface the game is played on, i.e. what happens when a snake moves over an edge of the
+/// board.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Topology {
+    /// Both axes wrap around
+    #[default]
+    Torus,
+    /// No wrapping, moving off the board is fatal
+    Box,
+    /// Horizontal axis wraps around, moving off the top or bottom is fatal
+    Cylinder,
+    /// Horizontal axis wraps around, vertical axis wraps around with the x coordinate mirrored
+    KleinBottle,
+    /// Both axes wrap around with the other coordinate mirrored
+    ProjectivePlane,
+}
+
+impl Topology {
+    /// Coordinates of the cell reached when moving one step in the given direction on a board of
+    /// the given size. Returns `None` if the move leaves the board.
+    pub fn shift_coords(
+        &self,
+        (x, y): (usize, usize),
+        direction: Direction,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let (dx, dy) = direction.coord_shift();
+        let new_x = x as i64 + dx as i64;
+        let new_y = y as i64 + dy as i64;
+        let crosses_x = new_x < 0 || new_x >= width as i64;
+        let crosses_y = new_y < 0 || new_y >= height as i64;
+        let wrapped_x = new_x.rem_euclid(width as i64) as usize;
+        let wrapped_y = new_y.rem_euclid(height as i64) as usize;
+
+        // a single step can only cross one edge, so at most one of crosses_x and crosses_y is true
+        match self {
+            Self::Torus => Some((wrapped_x, wrapped_y)),
+            _ if !crosses_x && !crosses_y => Some((wrapped_x, wrapped_y)),
+            Self::Box => None,
+            Self::Cylinder => (!crosses_y).then_some((wrapped_x, wrapped_y)),
+            Self::KleinBottle if crosses_y => Some((width - 1 - wrapped_x, wrapped_y)),
+            Self::KleinBottle => Some((wrapped_x, wrapped_y)),
+            Self::ProjectivePlane if crosses_y => Some((width - 1 - wrapped_x, wrapped_y)),
+            Self::ProjectivePlane => Some((wrapped_x, height - 1 - wrapped_y)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown topology, expected one of torus, box, cylinder, klein or projective")]
+pub struct InvalidTopology;
+
+impl std::str::FromStr for Topology {
+    type Err = InvalidTopology;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "torus" => Ok(Self::Torus),
+            "box" => Ok(Self::Box),
+            "cylinder" => Ok(Self::Cylinder),
+            "klein" => Ok(Self::KleinBottle),
+            "projective" => Ok(Self::ProjectivePlane),
+            _ => Err(InvalidTopology),
+        }
+    }
+}
+
+impl std::fmt::Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Torus => "torus",
+                Self::Box => "box",
+                Self::Cylinder => "cylinder",
+                Self::KleinBottle => "klein",
+                Self::ProjectivePlane => "projective",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction::*;
+    use Topology::*;
+
+    #[test]
+    fn inside_board() {
+        for topology in [Torus, Box, Cylinder, KleinBottle, ProjectivePlane] {
+            assert_eq!(topology.shift_coords((2, 2), East, 5, 4), Some((3, 2)));
+        }
+    }
+
+    #[test]
+    fn over_edges() {
+        assert_eq!(Torus.shift_coords((0, 1), West, 5, 4), Some((4, 1)));
+        assert_eq!(Box.shift_coords((0, 1), West, 5, 4), None);
+        assert_eq!(Cylinder.shift_coords((0, 1), West, 5, 4), Some((4, 1)));
+        assert_eq!(Cylinder.shift_coords((1, 3), South, 5, 4), None);
+        assert_eq!(KleinBottle.shift_coords((1, 3), South, 5, 4), Some((3, 0)));
+        assert_eq!(KleinBottle.shift_coords((4, 1), East, 5, 4), Some((0, 1)));
+        assert_eq!(
+            ProjectivePlane.shift_coords((1, 0), North, 5, 4),
+            Some((3, 3))
+        );
+        assert_eq!(
+            ProjectivePlane.shift_coords((4, 1), East, 5, 4),
+            Some((0, 2))
+        );
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        for topology in [Torus, Box, Cylinder, KleinBottle, ProjectivePlane] {
+            assert_eq!(topology.to_string().parse::<Topology>().unwrap(), topology);
+        }
+    }
+}
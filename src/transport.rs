@@ -0,0 +1,503 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::{Child, ChildStderr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long connecting to a socket bot, or waiting for a bot to connect to the lobby, may take
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a bot that connects to the lobby may take to send its name
+const IDENTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum length of the line in which a bot sends its name
+const MAX_IDENTIFY_LENGTH: usize = 256;
+
+/// How many connections may send their name to the lobby at the same time. Any more are closed
+const MAX_IDENTIFYING: usize = 64;
+
+/// How many connections of a bot may wait in the lobby for its next games. Any more are closed
+const MAX_WAITING: usize = 4;
+
+/// How long writing to a socket bot may block before it is considered gone. Processes that stop
+/// reading are killed to unblock the writing thread, for sockets this is the only way out
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The other end of the connection to a bot
+pub(crate) enum Peer {
+    /// A child process, talked to through its stdin and stdout
+    Process(Child),
+    /// A bot listening on a socket, which is not managed by the runner
+    Socket(Socket),
+    /// A bot running in a thread of the runner, which ends once its input is closed
+    Thread(JoinHandle<()>),
+}
+
+/// A connected TCP or Unix domain socket
+pub(crate) enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+/// Everything needed to talk to a bot: what we write is its input, what we read its output
+pub(crate) struct Connection {
+    pub input: Box<dyn Write + Send>,
+    pub output: Box<dyn BufRead + Send>,
+    /// Debug output, only for processes
+    pub stderr: Option<ChildStderr>,
+    pub peer: Peer,
+}
+
+impl Connection {
+    pub(crate) fn process(mut child: Child) -> Self {
+        Self {
+            input: Box::new(child.stdin.take().expect("Child has no stdin")),
+            output: Box::new(BufReader::new(
+                child.stdout.take().expect("Child has no stdout"),
+            )),
+            stderr: child.stderr.take(),
+            peer: Peer::Process(child),
+        }
+    }
+
+    /// Runs a bot in a thread of the runner, which reads the input of the bot from the first
+    /// argument of `bot` and writes its output to the second
+    pub(crate) fn thread<F>(bot: F) -> io::Result<Self>
+    where
+        F: FnOnce(BufReader<PipeReader>, PipeWriter) + Send + 'static,
+    {
+        let (bot_input, input) = io::pipe()?;
+        let (output, bot_output) = io::pipe()?;
+        let handle = thread::spawn(move || bot(BufReader::new(bot_input), bot_output));
+        Ok(Self {
+            input: Box::new(input),
+            output: Box::new(BufReader::new(output)),
+            stderr: None,
+            peer: Peer::Thread(handle),
+        })
+    }
+
+    pub(crate) fn socket(socket: Socket) -> io::Result<Self> {
+        Ok(Self {
+            input: Box::new(SocketWriter(socket.try_clone()?)),
+            output: Box::new(BufReader::new(socket.try_clone()?)),
+            stderr: None,
+            peer: Peer::Socket(socket),
+        })
+    }
+}
+
+impl Socket {
+    /// Connects to a TCP socket, e.g. `127.0.0.1:9000`
+    pub(crate) fn tcp(address: &str) -> io::Result<Self> {
+        let mut last_error = None;
+        for address in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Self::from_tcp(stream),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "address could not be resolved")
+        }))
+    }
+
+    fn from_tcp(stream: TcpStream) -> io::Result<Self> {
+        // moves are tiny messages that should not wait for more data
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Self::Tcp(stream))
+    }
+
+    /// Connects to a Unix domain socket
+    #[cfg(unix)]
+    pub(crate) fn unix(path: &Path) -> io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Self::Unix(stream))
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn unix(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not supported on this platform",
+        ))
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+        }
+    }
+
+    /// Closes the connection in one or both directions. Reading or writing in a closed direction
+    /// fails or returns the end of the stream, also in other threads
+    pub(crate) fn shutdown(&self, how: Shutdown) {
+        let _ = match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(how),
+        };
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+/// Writing half of a socket. Dropping it tells the bot that no more input follows, like closing
+/// the stdin of a process does
+struct SocketWriter(Socket);
+
+impl Write for SocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Socket::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Socket::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Drop for SocketWriter {
+    fn drop(&mut self) {
+        self.0.shutdown(Shutdown::Write);
+    }
+}
+
+/// A TCP socket on which the runner waits for bots to connect, instead of connecting to them.
+/// Every bot sends `name:{name}` as the first line after connecting, and connects again for
+/// every game. Connections are accepted and identified in the background, and kept until a game
+/// needs the bot with that name.
+pub struct Lobby {
+    address: SocketAddr,
+    state: Arc<LobbyState>,
+}
+
+/// What the lobby shares with the threads that accept and identify connections
+#[derive(Default)]
+struct LobbyState {
+    waiting: Mutex<Waiting>,
+    /// Notified whenever a bot is added to `waiting`
+    arrived: Condvar,
+    /// Number of connections that have not sent their name yet
+    identifying: AtomicUsize,
+    closed: AtomicBool,
+}
+
+/// Bots that connected to the lobby
+#[derive(Default)]
+struct Waiting {
+    /// Names of the bots, in the order in which they identified themselves
+    names: Vec<String>,
+    /// Whether `gather` is done, after which only the bots in `names` are let in
+    gathered: bool,
+    /// Connections of every bot, waiting for its next games
+    sockets: HashMap<String, VecDeque<Socket>>,
+}
+
+impl Waiting {
+    /// Keeps the connection of the bot until a game needs it. Returns whether it was kept: while
+    /// gathering, a bot cannot take a name that is already taken, and after that, no new bots
+    /// are let in and every bot has at most `MAX_WAITING` connections waiting.
+    fn admit(&mut self, name: String, socket: Socket) -> bool {
+        if !self.names.contains(&name) {
+            if self.gathered {
+                return false;
+            }
+            self.names.push(name.clone());
+        } else if !self.gathered {
+            return false;
+        }
+        let sockets = self.sockets.entry(name).or_default();
+        if sockets.len() >= MAX_WAITING {
+            return false;
+        }
+        sockets.push_back(socket);
+        true
+    }
+}
+
+impl std::fmt::Debug for Lobby {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lobby")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lobbies are only equal to themselves, so that bots waiting in the same lobby compare equal
+impl PartialEq for Lobby {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Lobby {
+    /// Listens on the address, and starts letting bots in
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(LobbyState::default());
+        let accepting = Arc::clone(&state);
+        thread::spawn(move || accept(&listener, &accepting));
+        Ok(Self { address, state })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+
+    /// Waits until `n_bots` bots with different names have connected, and returns their names in
+    /// the order in which they identified themselves. Bots that use a name that is already taken
+    /// are disconnected, and so are all bots with other names from then on.
+    pub fn gather(&self, n_bots: usize) -> Vec<String> {
+        let mut waiting = self.lock();
+        while waiting.names.len() < n_bots {
+            waiting = self
+                .state
+                .arrived
+                .wait(waiting)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        waiting.gathered = true;
+        for name in waiting.names.split_off(n_bots) {
+            waiting.sockets.remove(&name);
+        }
+        waiting.names.clone()
+    }
+
+    /// Connection of the bot with the given name, waiting at most `timeout` for it to connect if
+    /// it has not yet
+    pub(crate) fn take(&self, name: &str, timeout: Duration) -> io::Result<Socket> {
+        let deadline = Instant::now() + timeout;
+        let mut waiting = self.lock();
+        loop {
+            if let Some(socket) = waiting.sockets.get_mut(name).and_then(VecDeque::pop_front) {
+                return Ok(socket);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{name} did not connect"),
+                ));
+            }
+            waiting = self
+                .state
+                .arrived
+                .wait_timeout(waiting, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Waiting> {
+        // the bots stay consistent even if a thread panicked while holding the lock
+        self.state
+            .waiting
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Lobby {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::Release);
+        // wake up the thread accepting connections, so that it sees that the lobby is closed
+        let mut address = self.address;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let _ = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT);
+    }
+}
+
+/// Accepts connections until the lobby is closed, and identifies every one of them in a thread of
+/// its own, so that a bot that is slow to send its name does not hold up the others
+fn accept(listener: &TcpListener, state: &Arc<LobbyState>) {
+    for stream in listener.incoming() {
+        if state.closed.load(Ordering::Acquire) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            // e.g. out of file descriptors, which may be over soon
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        };
+        if state.identifying.fetch_add(1, Ordering::AcqRel) >= MAX_IDENTIFYING {
+            state.identifying.fetch_sub(1, Ordering::AcqRel);
+            continue;
+        }
+        let state = Arc::clone(state);
+        thread::spawn(move || {
+            if let Some((name, socket)) = identify(stream) {
+                let mut waiting = state.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+                if waiting.admit(name, socket) {
+                    state.arrived.notify_all();
+                }
+            }
+            state.identifying.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+}
+
+/// Reads the `name:{name}` line a bot sends after connecting to the lobby. `None` if it sends
+/// anything else or takes longer than `IDENTIFY_TIMEOUT` for the whole line
+fn identify(stream: TcpStream) -> Option<(String, Socket)> {
+    let deadline = Instant::now() + IDENTIFY_TIMEOUT;
+    // byte by byte, so that nothing after the line is read before the game starts
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while line.len() < MAX_IDENTIFY_LENGTH {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+        stream.set_read_timeout(Some(remaining)).ok()?;
+        match (&stream).read(&mut byte) {
+            Ok(1) if byte[0] == b'\n' => break,
+            Ok(1) => line.push(byte[0]),
+            _ => return None,
+        }
+    }
+    stream.set_read_timeout(None).ok()?;
+    let line = String::from_utf8(line).ok()?;
+    let name = line.trim().strip_prefix("name:")?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), Socket::from_tcp(stream).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcp_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let bot = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"N\n").unwrap();
+            let mut input = String::new();
+            stream.read_to_string(&mut input).unwrap();
+            input
+        });
+
+        let mut connection = Connection::socket(Socket::tcp(&address).unwrap()).unwrap();
+        let mut line = String::new();
+        connection.output.read_line(&mut line).unwrap();
+        assert_eq!(line, "N\n");
+        connection.input.write_all(b"stop\n").unwrap();
+        // dropping the input ends the input of the bot
+        drop(connection.input);
+        assert_eq!(bot.join().unwrap(), "stop\n");
+    }
+
+    #[test]
+    fn thread_connection() {
+        let mut connection = Connection::thread(|input, mut output| {
+            for line in input.lines() {
+                writeln!(output, "{}", line.unwrap().to_uppercase()).unwrap();
+            }
+        })
+        .unwrap();
+        connection.input.write_all(b"n\n").unwrap();
+        let mut line = String::new();
+        connection.output.read_line(&mut line).unwrap();
+        assert_eq!(line, "N\n");
+
+        // the thread ends once its input is closed
+        drop(connection.input);
+        let Peer::Thread(handle) = connection.peer else {
+            panic!("not a thread");
+        };
+        handle.join().unwrap();
+        line.clear();
+        assert_eq!(connection.output.read_line(&mut line).unwrap(), 0);
+    }
+
+    #[test]
+    fn lobby() {
+        let lobby = Lobby::bind("127.0.0.1:0").unwrap();
+        let address = lobby.local_addr().unwrap();
+        let connect = move |line: &'static str| {
+            thread::spawn(move || {
+                let mut stream = TcpStream::connect(address).unwrap();
+                stream.write_all(line.as_bytes()).unwrap();
+                let mut input = String::new();
+                stream.read_to_string(&mut input).unwrap();
+                input
+            })
+        };
+        let bots = [
+            connect("name:a\n"),
+            connect("hello\n"), // does not identify, so it is ignored
+            connect("name:b\n"),
+        ];
+        let mut names = lobby.gather(2);
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+
+        let timeout = Duration::from_millis(50);
+        let b = Connection::socket(lobby.take("b", timeout).unwrap()).unwrap();
+        let a = Connection::socket(lobby.take("a", timeout).unwrap()).unwrap();
+        for (mut connection, message) in [(a, "0\n"), (b, "1\n")] {
+            connection.input.write_all(message.as_bytes()).unwrap();
+        }
+        let [a, hello, b] = bots.map(|bot| bot.join().unwrap());
+        assert_eq!((a.as_str(), hello.as_str(), b.as_str()), ("0\n", "", "1\n"));
+        assert!(lobby.take("a", timeout).is_err()); // did not connect again
+    }
+
+    #[test]
+    fn lobby_not_held_up() {
+        let lobby = Lobby::bind("127.0.0.1:0").unwrap();
+        let address = lobby.local_addr().unwrap();
+        let connect = |line: &[u8]| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(line).unwrap();
+            stream
+        };
+        // bots that do not send their name, or only part of it, do not keep others out
+        let _silent = connect(b"");
+        let _slow = connect(b"na");
+        let _bots = [connect(b"name:a\n"), connect(b"name:b\n")];
+        let start = Instant::now();
+        let mut names = lobby.gather(2);
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+        assert!(start.elapsed() < IDENTIFY_TIMEOUT);
+        // a bot that nobody asked for is disconnected
+        let mut unknown = connect(b"name:c\n");
+        let mut input = Vec::new();
+        assert_eq!(unknown.read_to_end(&mut input).unwrap(), 0);
+    }
+}
@@ -3,10 +3,11 @@
 # class to keep track of game state
 class TorusSnakeGame:
     direction_to_coord_shift = {"N": (0,-1), "S": (0,1), "E": (1,0), "W": (-1,0)}
-    def __init__(self,width, height, starting_positions):
-        self.board = [[-1 for i in range(width)] for j in range(height)]  # -1 is empty, otherwise contains player number
+    def __init__(self,width, height, starting_positions, topology="torus"):
+        self.board = [[-1 for i in range(width)] for j in range(height)]  # -1 is empty, -2 is a wall, otherwise contains player number
         self.width = width
         self.height = height
+        self.topology = topology  # torus, box, cylinder, klein or projective
         self.head_positions = starting_positions  # positions of snake heads
         self.n_players = len(starting_positions)
         self.alive_players = set(range(self.n_players))
@@ -20,17 +21,27 @@ class TorusSnakeGame:
         self.board[y][x] = player
     
     def shift_coords(self, x, y, direction): 
-        """Returns coordinates of position (x,y) shifted one step in the given direction"""
+        """Returns coordinates of position (x,y) shifted one step in the given direction, or None if that leaves the board"""
         dx, dy = self.direction_to_coord_shift[direction]
+        crosses_x = not 0 <= x + dx < self.width
+        crosses_y = not 0 <= y + dy < self.height
         new_x = (x + dx) % self.width
         new_y = (y + dy) % self.height
+        if self.topology == "torus" or not (crosses_x or crosses_y):
+            return new_x, new_y
+        if self.topology == "box" or (self.topology == "cylinder" and crosses_y):
+            return None
+        if crosses_y and self.topology in ("klein", "projective"):
+            return self.width - 1 - new_x, new_y
+        if crosses_x and self.topology == "projective":
+            return new_x, self.height - 1 - new_y
         return new_x, new_y
 
     def move_player(self, player, direction):
         """Moves player in given direction if possible and returns True. If instead the cell is occupied,
         does nothing and returns False"""
         new_pos = self.shift_coords(*self.head_positions[player], direction)
-        if self.get_cell(*new_pos) == -1:
+        if new_pos is not None and self.get_cell(*new_pos) == -1:
             self.head_positions[player] = new_pos
             self.set_cell(*new_pos, player)
             return True
@@ -41,6 +52,8 @@ class TorusSnakeGame:
         Empty cells are displayed as dots"""
         if self.get_cell(x,y) == -1:
             return "·"
+        if self.get_cell(x,y) == -2:
+            return "#"
 
         player = self.get_cell(x,y)
         if self.head_positions[player] == (x,y):
@@ -59,30 +72,47 @@ class TorusSnakeGame:
         return f"TorusSnakeGame(width={self.width}, height={self.height}, n_players={self.n_players},\n\t\thead_positions={self.head_positions},\n\t\talive_players={self.alive_players},\n\t\tboard:\n\t\t{boardrepr})"
     
 
+# answering the handshake: accept the optional header lines this script understands, and give it a name
+line = input()
+if line.startswith("snakerunner:"):
+    print("hello:2:time_bank,topology,map:unidirectionalmover")
+    line = input()
+
 # parsing header
-width, height = [int(s) for s in input().split(",")] # width and height of board
+width, height = [int(s) for s in line.split(",")] # width and height of board
 n_players = int(input()) # number of players
 starting_positions = []
 for i in range(n_players):
     starting_positions.append(tuple(int(s) for s in input().split(",")))
-my_player_number = int(input())
+# optional header lines (e.g. topology:box), followed by our player number
+options = {}
+line = input()
+while ":" in line:
+    key, value = line.split(":", 1)
+    options[key] = value
+    line = input()
+my_player_number = int(line)
 
 
 # playing the game
-game = TorusSnakeGame(width, height, starting_positions)
+game = TorusSnakeGame(width, height, starting_positions, options.get("topology", "torus"))
+for wall in options.get("walls", "").split():
+    game.set_cell(*(int(s) for s in wall.split(",")), -2)  # -2 is a wall
 directions = ["N", "S", "E", "W"]
 while True:
     instruction = input()
-    if instruction == "stop":
+    if instruction.startswith("stop"):  # followed by the reason when we lost
         break
-    elif instruction == "move":
+    elif instruction.startswith("move"):  # followed by the remaining time when playing with a time control
         for i in directions:
             move = i
             if game.move_player(my_player_number, i):
                 break
         print(move)
+    elif instruction[:4] == "food":
+        pass  # this script does not model the classic rules, so it treats cells left behind by tails as occupied
     elif instruction[:3] == "out":
-        dead_player = int(instruction[4:])
+        dead_player = int(instruction.split(":")[1])  # followed by the reason
         game.alive_players.remove(dead_player)
     else: # instruction must be a move from another player
         player_number, direction = instruction.split(":")
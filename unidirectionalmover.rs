@@ -179,11 +179,15 @@ impl FromStr for Instruction {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use Instruction::*;
         match s {
-            "stop" => Ok(Stop),
-            "move" => Ok(AskMove),
+            instr if instr.starts_with("stop") => Ok(Stop), // ignores the reason
+            instr if instr.starts_with("move") => Ok(AskMove), // ignores the remaining time
             instr if &instr[..3] == "out" => {
                 // if instruction starts with "out"
+                // ignores the reason
                 let player = *&instr[4..]
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
                     .parse::<usize>()
                     .map_err(|_| "Could not parse player number".to_string())?;
                 Ok(Out { player })
@@ -198,7 +202,13 @@ impl FromStr for Instruction {
 
 fn main() {
     let mut lines = io::stdin().lines();
-    let (width, height) = parse_usize_pair(&lines.next().unwrap().unwrap()).unwrap();
+    // answer the handshake without accepting any optional header lines
+    let mut line = lines.next().unwrap().unwrap();
+    if line.starts_with("snakerunner:") {
+        println!("hello:2::unidirectionalmover");
+        line = lines.next().unwrap().unwrap();
+    }
+    let (width, height) = parse_usize_pair(&line).unwrap();
     let n_players: usize = lines.next().unwrap().unwrap().parse().unwrap();
     let starting_positions: Vec<(usize, usize)> = (0..n_players)
         .map(|_| parse_usize_pair(&lines.next().unwrap().unwrap()).unwrap())